@@ -1,4 +1,12 @@
 cargo build
 # cp target/dutchman_game*.dll ./dutchman_game.dll
-rm ./dutchman_game*.dll
-cp target/dutchman_game*.dll ./dutchman_game-${RANDOM}.dll
+# NOTE target/ can hold artifacts from older builds too, so only copy the
+# newest one.
+if ls target/libdutchman_game*.so >/dev/null 2>&1; then
+    # NOTE don't remove the old .so here; the host unlinks it once it has
+    # swapped over to the new one.
+    cp "$(ls -t target/libdutchman_game*.so | head -n 1)" ./libdutchman_game-${RANDOM}.so
+else
+    rm ./dutchman_game*.dll
+    cp "$(ls -t target/dutchman_game*.dll | head -n 1)" ./dutchman_game-${RANDOM}.dll
+fi
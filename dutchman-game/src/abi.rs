@@ -0,0 +1,213 @@
+use std::mem::{transmute, size_of};
+use std::raw;
+use std::slice::bytes::copy_memory;
+use std::str;
+#[cfg(test)]
+use platform;
+
+// NOTE bump this whenever an exported function (load, process_input, update,
+// render, layout_descriptor...) is added or changes signature.
+pub static ABI_VERSION: u32 = 20;
+
+// How many bytes the host sets aside for Game, Options and GlData. The
+// host refuses to load a library whose structs don't fit.
+// NOTE make sure these match the host's main.rs.
+pub const GAME_MEMORY_SIZE:    uint = 64 * 1024;
+pub const OPTIONS_MEMORY_SIZE: uint = 4096;
+pub const GL_MEMORY_SIZE:      uint = 1024;
+
+// NOTE make sure this matches LayoutDescriptor in the host's main.rs.
+#[repr(C)]
+#[deriving(Copy)]
+#[allow(missing_copy_implementations)]
+pub struct LayoutDescriptor {
+    pub abi_version: u32,
+
+    pub game_size: u64,
+    pub game_hash: u64,
+
+    pub options_size: u64,
+    pub options_hash: u64,
+    // How many bytes at the start of Options are input. Input loops,
+    // rollbacks and save states only put those back, leaving config and
+    // what the devices were doing as they are.
+    pub options_input_size: u64,
+
+    pub gl_data_size: u64,
+    pub gl_data_hash: u64
+}
+
+// FNV-1a over the stringified struct definition. This only sees the names
+// and types of the fields, not the layout of the types themselves, which is
+// why the sizes go in the descriptor as well.
+pub fn layout_hash(layout: &str) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in layout.bytes() {
+        hash ^= byte as u64;
+        hash *= 0x100000001b3;
+    }
+    hash
+}
+
+// text with the layout hash of every struct in nested (by name, then its
+// layout) that it mentions. Without this, moving the fields of a struct
+// around inside another one would go unnoticed.
+pub fn with_nested(text: &str, nested: &[(&str, &str)]) -> String {
+    let mut out = text.to_string();
+    for word in text.split(|c: char| !(c.is_alphanumeric() || c == '_')) {
+        match nested.iter().find(|&&(name, _)| name == word) {
+            Some(&(name, layout)) =>
+                out.push_str(format!(" {}={:x}", name, layout_hash(layout)).as_slice()),
+            None => {}
+        }
+    }
+    out
+}
+
+// === Migration ===
+// When Game changes layout, the old library saves it as a list of tagged
+// fields:
+//     [name len: u32][name][type len: u32][type][data len: u32][data]
+// and the new library copies back every field whose name, type and size
+// still match. Anything else keeps whatever a fresh start would give it.
+// NOTE types go through with_nested, so they change along with the structs
+// they mention.
+
+unsafe fn raw_bytes<'a, T>(value: &'a T) -> &'a [u8] {
+    transmute(raw::Slice { data: value as *const T as *const u8, len: size_of::<T>() })
+}
+
+unsafe fn raw_bytes_mut<'a, T>(value: &'a mut T) -> &'a mut [u8] {
+    transmute(raw::Slice { data: value as *mut T as *const u8, len: size_of::<T>() })
+}
+
+pub struct FieldWriter<'a> {
+    buf: &'a mut [u8],
+    pub len: uint,
+    pub overflowed: bool
+}
+
+impl<'a> FieldWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> FieldWriter<'a> {
+        FieldWriter { buf: buf, len: 0, overflowed: false }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        if self.overflowed || self.len + 4 + bytes.len() > self.buf.len() {
+            self.overflowed = true;
+            return;
+        }
+
+        let len = bytes.len() as u32;
+        for i in range(0u, 4) {
+            self.buf[self.len + i] = (len >> (i * 8)) as u8;
+        }
+        self.len += 4;
+
+        copy_memory(self.buf.slice_from_mut(self.len), bytes);
+        self.len += bytes.len();
+    }
+
+    pub fn write<T>(&mut self, name: &str, typ: &str, value: &T) {
+        self.put(name.as_bytes());
+        self.put(typ.as_bytes());
+        self.put(unsafe { raw_bytes(value) });
+    }
+}
+
+pub struct SavedField<'a> {
+    pub name: &'a str,
+    pub typ:  &'a str,
+    pub data: &'a [u8]
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut uint) -> Option<&'a [u8]> {
+    if *pos + 4 > data.len() { return None; }
+
+    let mut len = 0u;
+    for i in range(0u, 4) {
+        len |= (data[*pos + i] as uint) << (i * 8);
+    }
+    *pos += 4;
+
+    if *pos + len > data.len() { return None; }
+    let bytes = data.slice(*pos, *pos + len);
+    *pos += len;
+    Some(bytes)
+}
+
+// Splits saved data back up into fields. Stops at the first malformed one.
+pub fn read_fields<'a>(data: &'a [u8]) -> Vec<SavedField<'a>> {
+    let mut fields = Vec::new();
+    let mut pos = 0u;
+
+    loop {
+        let name = match take(data, &mut pos).and_then(str::from_utf8) { Some(s) => s, None => break };
+        let typ  = match take(data, &mut pos).and_then(str::from_utf8) { Some(s) => s, None => break };
+        let data = match take(data, &mut pos) { Some(d) => d, None => break };
+
+        fields.push(SavedField { name: name, typ: typ, data: data });
+    }
+
+    fields
+}
+
+// Copies the saved field with the given name into value, if it still has
+// the same type and size. Returns whether it did.
+pub fn restore_field<T>(saved: &[SavedField], struct_name: &str,
+                        name: &str, typ: &str, value: &mut T) -> bool {
+    for field in saved.iter() {
+        if field.name != name { continue; }
+
+        if field.typ == typ && field.data.len() == size_of::<T>() {
+            unsafe { copy_memory(raw_bytes_mut(value), field.data); }
+            return true;
+        }
+        log!("{}.{} changed from {} to {}; starting it over.", struct_name, name, field.typ, typ);
+        return false;
+    }
+
+    log!("{}.{} is new; leaving it at its default.", struct_name, name);
+    false
+}
+
+#[test]
+fn fields_survive_a_round_trip() {
+    platform::set(&platform::TEST_SERVICES);
+
+    let mut buf = [0u8, ..64];
+    let len = {
+        let mut writer = FieldWriter::new(buf.as_mut_slice());
+        writer.write("a", "int", &5i);
+        writer.write("b", "u8", &7u8);
+        assert!(!writer.overflowed);
+        writer.len
+    };
+
+    let saved = read_fields(buf.slice_to(len));
+    let mut a = 0i;
+    let mut b = 0u16;
+    let mut c = 0u8;
+    assert!(restore_field(saved.as_slice(), "Test", "a", "int", &mut a));
+    assert!(!restore_field(saved.as_slice(), "Test", "b", "u16", &mut b));
+    assert!(!restore_field(saved.as_slice(), "Test", "c", "u8", &mut c));
+    assert_eq!(a, 5);
+    assert_eq!(b, 0);
+}
+
+#[test]
+fn layout_hash_sees_field_changes() {
+    assert!(layout_hash("Game { a: int, }") != layout_hash("Game { a: int, b: int, }"));
+    assert_eq!(layout_hash("Game { a: int, }"), layout_hash("Game { a: int, }"));
+}
+
+#[test]
+fn nested_layouts_go_with_the_types_that_mention_them() {
+    let before = [("Inner", "Inner { a: f32, b: f32, }")];
+    let after  = [("Inner", "Inner { b: f32, a: f32, }")];
+
+    assert!(with_nested("[Inner, ..2]", &before) != with_nested("[Inner, ..2]", &after));
+    assert_eq!(with_nested("Option<Inner>", &before), with_nested("Option<Inner>", &before));
+    // Only whole names count.
+    assert_eq!(with_nested("Outer { x: InnerMost, }", &before).as_slice(), "Outer { x: InnerMost, }");
+}
@@ -0,0 +1,265 @@
+// The things the player can do, whatever they're pressed with. Bindings
+// decides which keys, mouse buttons and gamepad buttons press which Action;
+// the rest of the game only ever asks about Actions.
+
+use cgmath::Vector2;
+use std::num::Float;
+
+#[deriving(Copy, PartialEq, Show)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    Debug,
+    Pause
+}
+
+// NOTE these have to be in the same order as Action.
+pub const ACTION_COUNT: uint = 7;
+pub static ACTIONS: [Action, ..ACTION_COUNT] = [
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Interact,
+    Action::Debug,
+    Action::Pause
+];
+
+impl Action {
+    pub fn index(self) -> uint { self as uint }
+
+    // What it's called in the bindings file and headless scripts.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveUp    => "move_up",
+            Action::MoveDown  => "move_down",
+            Action::MoveLeft  => "move_left",
+            Action::MoveRight => "move_right",
+            Action::Interact  => "interact",
+            Action::Debug     => "debug",
+            Action::Pause     => "pause"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|a| a.name() == name).map(|a| *a)
+    }
+}
+
+// Presses after a release closer together than this count towards the same
+// run of taps.
+pub static TAP_INTERVAL: f64 = 0.25;
+
+// NOTE times are seconds on the host's clock, which starts at some arbitrary
+// point.
+#[deriving(Copy)]
+pub struct Control {
+    pub last_frame: bool,
+    pub this_frame: bool,
+
+    // Went down at some point this frame, even if it's back up already.
+    pub pressed_this_frame: bool,
+    pub pressed_at:  f64,
+    pub released_at: f64,
+    // How many quick presses in a row, counting the latest.
+    pub taps: u32
+}
+
+impl Control {
+    pub fn down(&self) -> bool { self.this_frame }
+    pub fn up(&self) -> bool { !self.this_frame }
+    pub fn just_down(&self) -> bool { self.this_frame && !self.last_frame }
+    pub fn just_up(&self) -> bool { !self.this_frame && self.last_frame }
+
+    // Catches presses shorter than a frame, which just_down misses.
+    pub fn pressed_during_frame(&self) -> bool { self.pressed_this_frame }
+
+    // How long it's been held as of now, or how long it was held last time
+    // if it's up.
+    pub fn press_duration(&self, now: f64) -> f64 {
+        if self.this_frame {
+            now - self.pressed_at
+        } else {
+            (self.released_at - self.pressed_at).max(0.0)
+        }
+    }
+
+    pub fn tap_count(&self) -> u32 { self.taps }
+
+    fn press(&mut self, time: f64) {
+        let streak = self.taps > 0 && time - self.released_at <= TAP_INTERVAL;
+        self.taps = if streak { self.taps + 1 } else { 1 };
+        self.this_frame = true;
+        self.pressed_this_frame = true;
+        self.pressed_at = time;
+    }
+
+    fn release(&mut self, time: f64) {
+        self.this_frame = false;
+        self.released_at = time;
+    }
+}
+
+// One press or release, in the order they happened.
+#[deriving(Copy, Show)]
+pub struct ActionEvent {
+    pub action:  Action,
+    pub pressed: bool,
+    pub time:    f64
+}
+
+// More than this many in one frame get dropped from the queue, but still
+// press and release their actions.
+pub const MAX_ACTION_EVENTS: uint = 32;
+
+// NOTE all zeroes is a valid Actions with nothing held.
+pub struct Actions {
+    controls: [Control, ..ACTION_COUNT],
+    // Everything that happened this frame.
+    events:      [ActionEvent, ..MAX_ACTION_EVENTS],
+    event_count: uint,
+    // When this frame's input was read.
+    now: f64,
+    // The analog stick after its deadzone, y going up. Zero without one.
+    stick: Vector2<f32>
+}
+
+impl Actions {
+    pub fn new() -> Actions {
+        Actions {
+            controls: [Control {
+                last_frame: false, this_frame: false, pressed_this_frame: false,
+                pressed_at: 0.0, released_at: 0.0, taps: 0
+            }, ..ACTION_COUNT],
+            events:      [ActionEvent { action: Action::MoveUp, pressed: false, time: 0.0 },
+                          ..MAX_ACTION_EVENTS],
+            event_count: 0,
+            now:         0.0,
+            stick:       Vector2::new(0.0, 0.0)
+        }
+    }
+
+    pub fn get(&self, action: Action) -> &Control {
+        &self.controls[action.index()]
+    }
+
+    // Presses or releases an action as of the given time. Does nothing if
+    // it's already that way.
+    pub fn set_at(&mut self, action: Action, down: bool, time: f64) {
+        {
+            let control = &mut self.controls[action.index()];
+            if control.this_frame == down { return; }
+
+            if down { control.press(time); } else { control.release(time); }
+        }
+
+        if self.event_count < MAX_ACTION_EVENTS {
+            self.events[self.event_count] = ActionEvent { action: action, pressed: down, time: time };
+            self.event_count += 1;
+        }
+    }
+
+    pub fn set(&mut self, action: Action, down: bool) {
+        let now = self.now;
+        self.set_at(action, down, now);
+    }
+
+    pub fn down(&self, action: Action) -> bool { self.get(action).down() }
+    pub fn up(&self, action: Action) -> bool { self.get(action).up() }
+    pub fn just_down(&self, action: Action) -> bool { self.get(action).just_down() }
+    pub fn just_up(&self, action: Action) -> bool { self.get(action).just_up() }
+    pub fn pressed_during_frame(&self, action: Action) -> bool {
+        self.get(action).pressed_during_frame()
+    }
+    pub fn press_duration(&self, action: Action) -> f64 {
+        self.get(action).press_duration(self.now)
+    }
+    pub fn tap_count(&self, action: Action) -> u32 { self.get(action).tap_count() }
+
+    pub fn events(&self) -> &[ActionEvent] {
+        self.events.slice_to(self.event_count)
+    }
+
+    pub fn now(&self) -> f64 { self.now }
+
+    pub fn set_stick(&mut self, stick: Vector2<f32>) {
+        self.stick = stick;
+    }
+
+    // Which way the player wants to move, y going up. The stick's position if
+    // it's pushed at all, otherwise -1, 0 or 1 on each axis from the move
+    // actions.
+    pub fn movement(&self) -> Vector2<f32> {
+        if self.stick.x != 0.0 || self.stick.y != 0.0 {
+            return self.stick;
+        }
+
+        let mut movement = Vector2::new(0.0, 0.0);
+        if self.down(Action::MoveLeft)  { movement.x -= 1.0; }
+        if self.down(Action::MoveRight) { movement.x += 1.0; }
+        if self.down(Action::MoveUp)    { movement.y += 1.0; }
+        if self.down(Action::MoveDown)  { movement.y -= 1.0; }
+        movement
+    }
+
+    // Call once at the start of each frame, before anything gets pressed.
+    // now is when the frame's input was read.
+    pub fn next_frame(&mut self, now: f64) {
+        for control in self.controls.iter_mut() {
+            control.last_frame = control.this_frame;
+            control.pressed_this_frame = false;
+        }
+        self.event_count = 0;
+        self.now = now;
+    }
+
+    pub fn release_all(&mut self) {
+        for &action in ACTIONS.iter() {
+            self.set(action, false);
+        }
+    }
+}
+
+#[test]
+fn actions_remember_last_frame() {
+    let mut actions = Actions::new();
+    actions.set(Action::Pause, true);
+    assert!(actions.just_down(Action::Pause));
+
+    actions.next_frame(0.0);
+    assert!(actions.down(Action::Pause) && !actions.just_down(Action::Pause));
+
+    actions.next_frame(0.0);
+    actions.release_all();
+    assert!(actions.just_up(Action::Pause));
+    assert_eq!(Action::from_name("move_left"), Some(Action::MoveLeft));
+    assert_eq!(Action::from_name("left"), None);
+}
+
+#[test]
+fn quick_presses_are_not_lost() {
+    let mut actions = Actions::new();
+
+    // Tapped twice within one frame.
+    actions.next_frame(1.0);
+    actions.set_at(Action::Interact, true,  0.90);
+    actions.set_at(Action::Interact, false, 0.92);
+    actions.set_at(Action::Interact, true,  0.95);
+    actions.set_at(Action::Interact, false, 0.97);
+    assert!(actions.up(Action::Interact) && !actions.just_down(Action::Interact));
+    assert!(actions.pressed_during_frame(Action::Interact));
+    assert_eq!(actions.tap_count(Action::Interact), 2);
+    assert_eq!(actions.events().len(), 4);
+    assert!((actions.press_duration(Action::Interact) - 0.02).abs() < 1e-9);
+
+    // Too long after for a third tap.
+    actions.next_frame(2.0);
+    assert!(!actions.pressed_during_frame(Action::Interact));
+    assert_eq!(actions.events().len(), 0);
+    actions.set_at(Action::Interact, true, 1.5);
+    assert_eq!(actions.tap_count(Action::Interact), 1);
+    assert_eq!(actions.press_duration(Action::Interact), 0.5);
+}
@@ -0,0 +1,233 @@
+// Named runs of frames with how long to show each one, and Animators that
+// play them. Clips are plain data so they can live in the assets arena
+// alongside the frames they point at; Animators are small enough to go right
+// in Game.
+
+use std::num::Float;
+use std::str;
+
+// NOTE these are per clip. Longer names get cut off.
+pub const MAX_CLIP_FRAMES: uint = 16;
+pub const MAX_CLIP_NAME: uint = 24;
+
+#[deriving(Copy, PartialEq, Show)]
+pub enum Playback {
+    // Back to the first frame after the last.
+    Loop,
+    // Stays on the last frame.
+    Once,
+    // Back and forth, without showing either end twice in a row.
+    PingPong
+}
+
+#[deriving(Copy)]
+pub struct Clip {
+    name:     [u8, ..MAX_CLIP_NAME],
+    name_len: uint,
+
+    // Frames within the clip's sheet, not the whole atlas.
+    pub frames:      [i32, ..MAX_CLIP_FRAMES],
+    // In seconds, one for each frame.
+    pub durations:   [f32, ..MAX_CLIP_FRAMES],
+    pub frame_count: uint,
+    pub playback:    Playback
+}
+
+impl Clip {
+    pub fn new(name: &str, playback: Playback) -> Clip {
+        let mut clip = Clip {
+            name:        [0, ..MAX_CLIP_NAME],
+            name_len:    0,
+            frames:      [0, ..MAX_CLIP_FRAMES],
+            durations:   [0.0, ..MAX_CLIP_FRAMES],
+            frame_count: 0,
+            playback:    playback
+        };
+        for (to, from) in clip.name.iter_mut().zip(name.bytes()) {
+            *to = from;
+            clip.name_len += 1;
+        }
+        clip
+    }
+
+    pub fn name(&self) -> &str {
+        str::from_utf8(self.name.slice_to(self.name_len)).unwrap_or("")
+    }
+
+    // Returns false if the clip is already full.
+    pub fn push_frame(&mut self, frame: i32, duration: f32) -> bool {
+        if self.frame_count == MAX_CLIP_FRAMES { return false; }
+
+        self.frames[self.frame_count]    = frame;
+        self.durations[self.frame_count] = duration;
+        self.frame_count += 1;
+        true
+    }
+
+    pub fn frames(&self) -> &[i32] {
+        self.frames.slice_to(self.frame_count)
+    }
+}
+
+// Which clip in clips is called name.
+pub fn find_clip(clips: &[Clip], name: &str) -> Option<uint> {
+    clips.iter().position(|c| c.name() == name)
+}
+
+// Frames shorter than this get shown this long anyway, so a clip full of
+// zero durations can't spin forever.
+pub static MIN_FRAME_DURATION: f32 = 0.001;
+
+// What happened to an Animator's clip, by its index in the clips.
+#[deriving(Copy, PartialEq, Show)]
+pub enum ClipEvent {
+    // A Loop or PingPong clip went back to its first frame.
+    Looped(uint),
+    // A Once clip got to the end of its last frame. Only happens once per
+    // play.
+    Finished(uint)
+}
+
+// Where something is in which clip. Knows nothing about the clips
+// themselves, so hand it the same list every time.
+// NOTE all zeroes is a valid Animator that isn't playing anything.
+layout!(ANIMATOR_LAYOUT:
+#[deriving(Copy, Show)]
+pub struct Animator {
+    pub clip:     Option<uint>,
+    // Which of the clip's frames, not which frame of the sheet.
+    pub position: uint,
+    // Seconds spent on the current frame so far.
+    pub time:     f32,
+    // PingPong clips on the way back.
+    pub backwards: bool,
+    pub finished:  bool,
+});
+
+impl Animator {
+    pub fn new() -> Animator {
+        Animator { clip: None, position: 0, time: 0.0, backwards: false, finished: false }
+    }
+
+    // Starts the clip from the beginning, unless it's already playing.
+    pub fn play(&mut self, clip: uint) {
+        if self.clip != Some(clip) {
+            self.restart(clip);
+        }
+    }
+
+    pub fn restart(&mut self, clip: uint) {
+        *self = Animator { clip: Some(clip), .. Animator::new() };
+    }
+
+    // The sheet frame to draw, or None if there's nothing to play.
+    pub fn frame(&self, clips: &[Clip]) -> Option<i32> {
+        match self.clip {
+            Some(i) if i < clips.len() && clips[i].frame_count > 0 =>
+                Some(clips[i].frames[self.position.min(clips[i].frame_count - 1)]),
+            _ => None
+        }
+    }
+
+    // Moves delta seconds through the clip. If it looped or finished on the
+    // way, says so; should it loop more than once, that's still one event.
+    pub fn advance(&mut self, clips: &[Clip], delta: f32) -> Option<ClipEvent> {
+        let index = match self.clip {
+            Some(i) if i < clips.len() => i,
+            _ => return None
+        };
+        let clip = &clips[index];
+        if clip.frame_count == 0 || self.finished { return None; }
+
+        let mut event = None;
+        self.time += delta;
+        loop {
+            let duration = clip.durations[self.position].max(MIN_FRAME_DURATION);
+            if self.time < duration { break; }
+            self.time -= duration;
+
+            match self.step(clip, index) {
+                Some(e) => event = Some(e),
+                None    => {}
+            }
+            if self.finished {
+                self.time = 0.0;
+                break;
+            }
+        }
+        event
+    }
+
+    // On to the next frame.
+    fn step(&mut self, clip: &Clip, index: uint) -> Option<ClipEvent> {
+        let last = clip.frame_count - 1;
+        match clip.playback {
+            Playback::Loop if self.position >= last => {
+                self.position = 0;
+                Some(ClipEvent::Looped(index))
+            }
+            Playback::Once if self.position >= last => {
+                self.finished = true;
+                Some(ClipEvent::Finished(index))
+            }
+            Playback::Loop | Playback::Once => {
+                self.position += 1;
+                None
+            }
+
+            Playback::PingPong if last == 0 => Some(ClipEvent::Looped(index)),
+            Playback::PingPong if self.backwards => {
+                self.position -= 1;
+                if self.position == 0 {
+                    self.backwards = false;
+                    Some(ClipEvent::Looped(index))
+                } else { None }
+            }
+            Playback::PingPong => {
+                self.position += 1;
+                if self.position == last { self.backwards = true; }
+                None
+            }
+        }
+    }
+}
+
+#[test]
+fn clips_loop_bounce_and_finish() {
+    let mut walk = Clip::new("walk", Playback::Loop);
+    let mut bounce = Clip::new("bounce", Playback::PingPong);
+    let mut die = Clip::new("die", Playback::Once);
+    for &frame in [4, 5, 6].iter() {
+        walk.push_frame(frame, 0.1);
+        bounce.push_frame(frame, 0.1);
+        die.push_frame(frame, 0.1);
+    }
+    let clips = [walk, bounce, die];
+    assert_eq!(find_clip(&clips, "bounce"), Some(1));
+
+    let mut animator = Animator::new();
+    assert_eq!(animator.frame(&clips), None);
+
+    animator.play(0);
+    assert_eq!(animator.advance(&clips, 0.25), None);
+    assert_eq!(animator.frame(&clips), Some(6));
+    assert_eq!(animator.advance(&clips, 0.1), Some(ClipEvent::Looped(0)));
+    assert_eq!(animator.frame(&clips), Some(4));
+    // Playing what's already playing doesn't start it over.
+    animator.advance(&clips, 0.1);
+    animator.play(0);
+    assert_eq!(animator.frame(&clips), Some(5));
+
+    animator.play(1);
+    let mut shown = Vec::new();
+    for _ in range(0u, 6) {
+        shown.push(animator.frame(&clips).unwrap());
+        animator.advance(&clips, 0.1);
+    }
+    assert_eq!(shown, vec![4, 5, 6, 5, 4, 5]);
+
+    animator.play(2);
+    assert_eq!(animator.advance(&clips, 0.5), Some(ClipEvent::Finished(2)));
+    assert_eq!(animator.frame(&clips), Some(6));
+    assert_eq!(animator.advance(&clips, 0.5), None);
+}
@@ -0,0 +1,198 @@
+// Reads the JSON Aseprite writes out with File > Export Sprite Sheet, so new
+// art can be dropped in without counting frames by hand. Export with
+// "Array" as the JSON data format and tags turned on; every tag becomes a
+// Clip.
+// NOTE "Hash" exports key frames by filename, which doesn't keep their order.
+
+extern crate serialize;
+
+use self::serialize::json;
+use self::serialize::json::Json;
+use cgmath::Vector2;
+use std::str;
+
+use animation::{Clip, Playback, MAX_CLIP_FRAMES};
+use asset;
+use render::texture::FrameDef;
+
+pub struct Sheet {
+    // The image the frames are in, from the same directory.
+    pub image:  String,
+    pub frames: Vec<FrameDef>,
+    pub clips:  Vec<Clip>
+}
+
+fn number(json: &Json, path: &[&str], what: &str) -> Result<u64, String> {
+    match json.find_path(path).and_then(|n| n.as_u64()) {
+        Some(n) => Ok(n),
+        None    => Err(format!("{} doesn't have a {}", what, path.connect(".")))
+    }
+}
+
+fn text<'a>(json: &'a Json, key: &str, what: &str) -> Result<&'a str, String> {
+    match json.find(key).and_then(|s| s.as_string()) {
+        Some(s) => Ok(s),
+        None    => Err(format!("{} doesn't have a {}", what, key))
+    }
+}
+
+fn parse_frame(json: &Json, index: uint) -> Result<(FrameDef, f32), String> {
+    let what = format!("frame {}", index);
+    let what = what.as_slice();
+
+    let width  = try!(number(json, &["frame", "w"], what)) as u32;
+    let height = try!(number(json, &["frame", "h"], what)) as u32;
+    // Trimmed frames lose their empty edges, so put the pivot where the
+    // untrimmed frame's bottom left would be. That keeps them lined up.
+    let pivot = match (json.find_path(&["spriteSourceSize", "x"]).and_then(|n| n.as_i64()),
+                       json.find_path(&["spriteSourceSize", "y"]).and_then(|n| n.as_i64()),
+                       json.find_path(&["sourceSize", "h"]).and_then(|n| n.as_i64())) {
+        (Some(x), Some(y), Some(source_height)) => Vector2::new(-x as f32, (source_height - y) as f32),
+        _ => Vector2::new(0.0, height as f32)
+    };
+    // Flipping mirrors about the untrimmed frame's middle, so keep its width.
+    let (source_x, source_width) =
+        match (json.find_path(&["spriteSourceSize", "x"]).and_then(|n| n.as_i64()),
+               json.find_path(&["sourceSize", "w"]).and_then(|n| n.as_i64())) {
+            (Some(x), Some(source_width)) => (x as u32, source_width as u32),
+            _ => (0, width)
+        };
+
+    let def = FrameDef {
+        name:   match json.find("filename").and_then(|s| s.as_string()) {
+            Some(name) => name.to_string(),
+            None       => what.to_string()
+        },
+        x:      try!(number(json, &["frame", "x"], what)) as u32,
+        y:      try!(number(json, &["frame", "y"], what)) as u32,
+        width:  width,
+        height: height,
+        pivot:  pivot,
+        source_width: source_width,
+        source_x:     source_x
+    };
+    // Aseprite has these in milliseconds.
+    let duration = try!(number(json, &["duration"], what)) as f32 / 1000.0;
+    Ok((def, duration))
+}
+
+fn parse_tag(json: &Json, durations: &[f32]) -> Result<Clip, String> {
+    let name = try!(text(json, "name", "a tag"));
+    let what = format!("tag {}", name);
+    let what = what.as_slice();
+
+    let from = try!(number(json, &["from"], what)) as uint;
+    let to   = try!(number(json, &["to"], what)) as uint;
+    if from > to || to >= durations.len() {
+        return Err(format!("{} goes from frame {} to {}, but there are only {}",
+                           what, from, to, durations.len()));
+    }
+    if to - from + 1 > MAX_CLIP_FRAMES {
+        return Err(format!("{} has more than {} frames", what, MAX_CLIP_FRAMES));
+    }
+
+    // Newer versions of Aseprite can say how many times to repeat.
+    let once = json.find("repeat").and_then(|r| r.as_string()) == Some("1");
+    let direction = match json.find("direction").and_then(|d| d.as_string()) {
+        Some(d) => d,
+        None    => "forward"
+    };
+    let (playback, reverse) = match direction {
+        "forward"  => (if once { Playback::Once } else { Playback::Loop }, false),
+        "reverse"  => (if once { Playback::Once } else { Playback::Loop }, true),
+        "pingpong" => (Playback::PingPong, false),
+        _ => return Err(format!("{} has a direction of {}, which isn't supported", what, direction))
+    };
+
+    let mut clip = Clip::new(name, playback);
+    let mut frames: Vec<uint> = range(from, to + 1).collect();
+    if reverse { frames.reverse(); }
+    for &frame in frames.iter() {
+        clip.push_frame(frame as i32, durations[frame]);
+    }
+    Ok(clip)
+}
+
+pub fn parse(text: &str) -> Result<Sheet, String> {
+    let json = match json::from_str(text) {
+        Ok(json) => json,
+        Err(e)   => return Err(format!("bad JSON: {}", e))
+    };
+
+    let frames_json = match json.find("frames") {
+        Some(f) => match f.as_array() {
+            Some(frames) => frames,
+            None => return Err("frames isn't a list. Export with \"Array\", not \"Hash\".".to_string())
+        },
+        None => return Err("there are no frames".to_string())
+    };
+    let mut frames    = Vec::new();
+    let mut durations = Vec::new();
+    for (index, frame_json) in frames_json.iter().enumerate() {
+        let (def, duration) = try!(parse_frame(frame_json, index));
+        frames.push(def);
+        durations.push(duration);
+    }
+
+    let mut clips = Vec::new();
+    match json.find_path(&["meta", "frameTags"]).and_then(|t| t.as_array()) {
+        Some(tags) => for tag in tags.iter() {
+            clips.push(try!(parse_tag(tag, durations.as_slice())));
+        },
+        None => {}
+    }
+
+    let image = match json.find_path(&["meta", "image"]).and_then(|i| i.as_string()) {
+        Some(image) => image.to_string(),
+        None        => return Err("meta doesn't have an image".to_string())
+    };
+
+    Ok(Sheet { image: image, frames: frames, clips: clips })
+}
+
+// Reads a sheet's JSON out of the assets directory.
+pub fn load(filename: &str) -> Sheet {
+    let bytes = match asset::read(filename) {
+        Some(bytes) => bytes,
+        None => panic!("Couldn't read sprite sheet {}", filename)
+    };
+    let text = match str::from_utf8(bytes.as_slice()) {
+        Some(text) => text,
+        None => panic!("{} isn't text", filename)
+    };
+    match parse(text) {
+        Ok(sheet) => sheet,
+        Err(e) => panic!("{}: {}", filename, e)
+    }
+}
+
+#[test]
+fn aseprite_tags_become_clips() {
+    let sheet = parse(r#"{ "frames": [
+        { "filename": "hero 0", "frame": { "x": 0, "y": 0, "w": 32, "h": 48 }, "duration": 100 },
+        { "filename": "hero 1", "frame": { "x": 32, "y": 0, "w": 32, "h": 48 }, "duration": 150 },
+        { "filename": "hero 2", "frame": { "x": 64, "y": 0, "w": 30, "h": 40 }, "duration": 100,
+          "trimmed": true, "spriteSourceSize": { "x": 2, "y": 8, "w": 30, "h": 40 },
+          "sourceSize": { "w": 32, "h": 48 } }
+      ],
+      "meta": { "image": "hero.png", "frameTags": [
+        { "name": "walk", "from": 0, "to": 1, "direction": "pingpong" },
+        { "name": "fall", "from": 1, "to": 2, "direction": "reverse" }
+      ] }
+    }"#).unwrap();
+
+    assert_eq!(sheet.image.as_slice(), "hero.png");
+    assert_eq!(sheet.frames.len(), 3);
+    assert_eq!(sheet.frames[1].x, 32);
+    assert_eq!(sheet.frames[0].pivot, Vector2::new(0.0, 48.0));
+    assert_eq!(sheet.frames[2].pivot, Vector2::new(-2.0, 40.0));
+    assert_eq!((sheet.frames[2].source_x, sheet.frames[2].source_width), (2, 32));
+
+    assert_eq!(sheet.clips[0].name(), "walk");
+    assert_eq!(sheet.clips[0].playback, Playback::PingPong);
+    assert_eq!(sheet.clips[0].durations.slice_to(2), [0.1, 0.15].as_slice());
+    assert_eq!(sheet.clips[1].frames(), [2, 1].as_slice());
+    assert_eq!(sheet.clips[1].playback, Playback::Loop);
+
+    assert!(parse(r#"{ "frames": {}, "meta": { "image": "hero.png" } }"#).is_err());
+}
@@ -1,15 +1,6 @@
-use std::os;
-// use std::io::fs::PathExtensions;
+use platform;
 
-pub fn path(asset_path: &str) -> Path {
-    let mut exe_path = match os::self_exe_path() {
-        Some(p) => p,
-        None => {
-            panic!("Couldn't get executable path :(");
-        }
-    };
-    exe_path.push("../assets");
-    exe_path.push(asset_path);
-
-    exe_path
+// Reads the named file out of the assets directory.
+pub fn read(asset_name: &str) -> Option<Vec<u8>> {
+    platform::read_file(platform::asset_path(asset_name).as_slice())
 }
@@ -0,0 +1,285 @@
+// Which buttons press which Actions. They're kept in a text file in the
+// config directory, one action per line followed by its buttons:
+//     # action  buttons
+//     move_up   W Up Axis1-
+//     interact  E MouseLeft
+// Edits to the file get picked up while the game runs.
+
+use actions::{Action, Actions, ACTIONS, ACTION_COUNT};
+use input::{Device, key, mouse, gamepad};
+use platform;
+
+pub static BINDINGS_FILE: &'static str = "bindings.txt";
+
+pub const MAX_BINDINGS_PER_ACTION: uint = 4;
+
+// How often to look at the file for changes.
+static CHECK_INTERVAL_NS: u64 = 500 * 1_000_000;
+
+#[deriving(Copy, PartialEq, Show)]
+pub struct Binding {
+    pub device: Device,
+    pub button: i32
+}
+
+impl Binding {
+    pub fn key(code: i32) -> Binding { Binding { device: Device::Keyboard, button: code } }
+    pub fn mouse(button: i32) -> Binding { Binding { device: Device::Mouse, button: button } }
+    pub fn gamepad(button: i32) -> Binding { Binding { device: Device::Gamepad, button: button } }
+    pub fn axis(axis: uint, positive: bool) -> Binding {
+        Binding { device: Device::GamepadAxis, button: gamepad::axis_button(axis, positive) }
+    }
+
+    pub fn from_name(name: &str) -> Option<Binding> {
+        key::from_name(name).map(|k| Binding::key(k))
+            .or_else(|| mouse::from_name(name).map(|b| Binding::mouse(b)))
+            .or_else(|| gamepad::from_name(name).map(|b| Binding::gamepad(b)))
+            .or_else(|| gamepad::axis_from_name(name)
+                             .map(|b| Binding { device: Device::GamepadAxis, button: b }))
+    }
+
+    pub fn name(&self) -> String {
+        match self.device {
+            Device::Keyboard    => key::name(self.button).unwrap_or(format!("{}", self.button)),
+            Device::Mouse       => mouse::name(self.button),
+            Device::Gamepad     => gamepad::name(self.button),
+            Device::GamepadAxis => gamepad::axis_name(self.button)
+        }
+    }
+}
+
+pub struct Bindings {
+    // One row per action, in ACTIONS order.
+    pub buttons: [[Option<Binding>, ..MAX_BINDINGS_PER_ACTION], ..ACTION_COUNT],
+    // Which of those buttons are down right now, so an action stays down
+    // until all of its buttons are let go.
+    pub held: [[bool, ..MAX_BINDINGS_PER_ACTION], ..ACTION_COUNT],
+
+    // Set by rebind so the file gets written back.
+    pub changed: bool,
+    // The file's modified time as of when we last read or wrote it.
+    pub file_time:     i64,
+    pub last_check_ns: u64
+}
+
+impl Bindings {
+    pub fn default() -> Bindings {
+        let mut bindings = Bindings {
+            buttons:       [[None, ..MAX_BINDINGS_PER_ACTION], ..ACTION_COUNT],
+            held:          [[false, ..MAX_BINDINGS_PER_ACTION], ..ACTION_COUNT],
+            changed:       false,
+            file_time:     -1,
+            last_check_ns: 0
+        };
+        let space = key::from_name("Space").unwrap();
+        // NOTE GLFW has the stick's y axis going down.
+        bindings.rebind(Action::MoveUp,    &[Binding::key(key::W), Binding::key(key::UP),
+                                             Binding::axis(1, false)]);
+        bindings.rebind(Action::MoveDown,  &[Binding::key(key::S), Binding::key(key::DOWN),
+                                             Binding::axis(1, true)]);
+        bindings.rebind(Action::MoveLeft,  &[Binding::key(key::A), Binding::key(key::LEFT),
+                                             Binding::axis(0, false)]);
+        bindings.rebind(Action::MoveRight, &[Binding::key(key::D), Binding::key(key::RIGHT),
+                                             Binding::axis(0, true)]);
+        bindings.rebind(Action::Interact,  &[Binding::key(key::E), Binding::key(space),
+                                             Binding::mouse(mouse::LEFT), Binding::gamepad(0)]);
+        bindings.rebind(Action::Debug,     &[Binding::key(key::B)]);
+        bindings.rebind(Action::Pause,     &[Binding::key(key::P), Binding::gamepad(7)]);
+        bindings.changed = false;
+        bindings
+    }
+
+    // Replaces the buttons for an action. Extra buttons past
+    // MAX_BINDINGS_PER_ACTION are dropped.
+    pub fn rebind(&mut self, action: Action, buttons: &[Binding]) {
+        let index = action.index();
+        self.buttons[index] = [None, ..MAX_BINDINGS_PER_ACTION];
+        self.held[index]    = [false, ..MAX_BINDINGS_PER_ACTION];
+        for (slot, &b) in self.buttons[index].iter_mut().zip(buttons.iter()) {
+            *slot = Some(b);
+        }
+        self.changed = true;
+    }
+
+    // Actions can only be mentioned once. Anything left out is unbound.
+    pub fn parse(text: &str) -> Result<Bindings, String> {
+        let mut bindings = Bindings::default();
+        bindings.buttons = [[None, ..MAX_BINDINGS_PER_ACTION], ..ACTION_COUNT];
+        let mut seen = [false, ..ACTION_COUNT];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("#") { continue; }
+
+            let mut words = line.words();
+            let name = words.next().unwrap();
+            let action = match Action::from_name(name) {
+                Some(a) => a,
+                None => return Err(format!("line {}: there's no action called {}", number + 1, name))
+            };
+            if seen[action.index()] {
+                return Err(format!("line {}: {} is already bound", number + 1, name));
+            }
+            seen[action.index()] = true;
+
+            let mut buttons = Vec::new();
+            for button_name in words {
+                match Binding::from_name(button_name) {
+                    Some(b) => buttons.push(b),
+                    None => return Err(format!("line {}: there's no button called {}",
+                                               number + 1, button_name))
+                }
+            }
+            if buttons.len() > MAX_BINDINGS_PER_ACTION {
+                return Err(format!("line {}: {} can only have {} buttons",
+                                   number + 1, name, MAX_BINDINGS_PER_ACTION));
+            }
+
+            bindings.rebind(action, buttons.as_slice());
+        }
+
+        bindings.changed = false;
+        Ok(bindings)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = "# action   buttons\n".to_string();
+        for (action, buttons) in ACTIONS.iter().zip(self.buttons.iter()) {
+            text.push_str(format!("{:<10}", action.name()).as_slice());
+            for b in buttons.iter().filter_map(|b| b.as_ref()) {
+                text.push(' ');
+                text.push_str(b.name().as_slice());
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Sets Actions according to a button going up or down at the given time.
+    pub fn apply(&mut self, actions: &mut Actions,
+                 device: Device, button: i32, pressed: bool, time: f64) {
+        let pressed_binding = Binding { device: device, button: button };
+        for &action in ACTIONS.iter() {
+            let index = action.index();
+            let mut bound = false;
+            for (b, held) in self.buttons[index].iter().zip(self.held[index].iter_mut()) {
+                if *b == Some(pressed_binding) {
+                    *held = pressed;
+                    bound = true;
+                }
+            }
+
+            if bound {
+                actions.set_at(action, self.held[index].iter().any(|h| *h), time);
+            }
+        }
+    }
+}
+
+fn file_path() -> String {
+    platform::config_path(BINDINGS_FILE)
+}
+
+// Reads the bindings file, or writes out the defaults if there isn't one.
+pub fn load() -> Bindings {
+    let path = file_path();
+
+    let bindings = match platform::read_file(path.as_slice()) {
+        None => {
+            log!("No {}; writing out the default bindings.", path);
+            let mut bindings = Bindings::default();
+            bindings.changed = true;
+            save_if_changed(&mut bindings);
+            return bindings;
+        }
+        Some(data) => Bindings::parse(String::from_utf8_lossy(data.as_slice()).as_slice())
+    };
+
+    match bindings {
+        Ok(mut bindings) => {
+            bindings.file_time = platform::modified_time(path.as_slice()).unwrap_or(-1);
+            bindings
+        }
+        Err(e) => {
+            log!("ERROR in {}: {}. Using the default bindings.", path, e);
+            let mut bindings = Bindings::default();
+            // Don't read it again until it's been fixed.
+            bindings.file_time = platform::modified_time(path.as_slice()).unwrap_or(-1);
+            bindings
+        }
+    }
+}
+
+pub fn save_if_changed(bindings: &mut Bindings) {
+    if !bindings.changed { return; }
+
+    let path = file_path();
+    if platform::write_file(path.as_slice(), bindings.to_text().as_bytes()) {
+        bindings.file_time = platform::modified_time(path.as_slice()).unwrap_or(-1);
+    } else {
+        log!("ERROR: Couldn't write bindings to {}", path);
+    }
+    bindings.changed = false;
+}
+
+// Reloads the bindings if the file was edited since we last looked. Returns
+// whether it did.
+pub fn reload_if_edited(bindings: &mut Bindings, actions: &mut Actions) -> bool {
+    let now = platform::precise_time_ns();
+    // NOTE last_check_ns can be ahead of now after loading a save state.
+    if now >= bindings.last_check_ns && now - bindings.last_check_ns < CHECK_INTERVAL_NS {
+        return false;
+    }
+    bindings.last_check_ns = now;
+
+    let path = file_path();
+    let time = platform::modified_time(path.as_slice()).unwrap_or(-1);
+    if time == bindings.file_time { return false; }
+
+    let text = match platform::read_file(path.as_slice()) {
+        Some(data) => String::from_utf8_lossy(data.as_slice()).into_string(),
+        None       => return false
+    };
+    bindings.file_time = time;
+
+    match Bindings::parse(text.as_slice()) {
+        Ok(new_bindings) => {
+            log!("Reloaded bindings from {}", path);
+            *bindings = Bindings { file_time: time, last_check_ns: now, .. new_bindings };
+            // Nothing is held as far as the new bindings know.
+            actions.release_all();
+            true
+        }
+        Err(e) => {
+            log!("ERROR in {}: {}. Keeping the old bindings.", path, e);
+            false
+        }
+    }
+}
+
+#[test]
+fn bindings_survive_a_round_trip() {
+    let mut bindings = Bindings::default();
+    bindings.rebind(Action::Debug, &[Binding::key(key::B), Binding::mouse(mouse::MIDDLE),
+                                     Binding::gamepad(3)]);
+
+    let parsed = Bindings::parse(bindings.to_text().as_slice()).unwrap();
+    assert_eq!(parsed.buttons, bindings.buttons);
+    assert!(Bindings::parse("jump Space").is_err());
+    assert!(Bindings::parse("move_up NotAKey").is_err());
+    assert!(Bindings::parse("debug B\ndebug MouseMiddle").is_err());
+}
+
+#[test]
+fn any_bound_button_holds_an_action_down() {
+    let mut bindings = Bindings::default();
+    let mut actions = Actions::new();
+
+    bindings.apply(&mut actions, Device::Keyboard, key::E, true, 0.0);
+    bindings.apply(&mut actions, Device::Mouse, mouse::LEFT, true, 0.1);
+    bindings.apply(&mut actions, Device::Keyboard, key::E, false, 0.2);
+    assert!(actions.down(Action::Interact));
+
+    bindings.apply(&mut actions, Device::Mouse, mouse::LEFT, false, 0.3);
+    assert!(actions.up(Action::Interact));
+}
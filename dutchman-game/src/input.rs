@@ -0,0 +1,243 @@
+// What the host tells us about the window each frame. The host does all the
+// event polling, so none of this depends on GLFW.
+
+// NOTE key codes are GLFW's, since that's what the host reads them from.
+pub mod key {
+    pub const A:      i32 = 65;
+    pub const B:      i32 = 66;
+    pub const D:      i32 = 68;
+    pub const E:      i32 = 69;
+    pub const P:      i32 = 80;
+    pub const S:      i32 = 83;
+    pub const W:      i32 = 87;
+    pub const ESCAPE: i32 = 256;
+    pub const RIGHT:  i32 = 262;
+    pub const LEFT:   i32 = 263;
+    pub const DOWN:   i32 = 264;
+    pub const UP:     i32 = 265;
+
+    // Keys with names longer than one character. Letters and digits are
+    // named after themselves.
+    static NAMED: [(&'static str, i32), ..17] = [
+        ("Space",        32),
+        ("Escape",       ESCAPE),
+        ("Enter",        257),
+        ("Tab",          258),
+        ("Backspace",    259),
+        ("Right",        RIGHT),
+        ("Left",         LEFT),
+        ("Down",         DOWN),
+        ("Up",           UP),
+        ("LeftShift",    340),
+        ("LeftControl",  341),
+        ("LeftAlt",      342),
+        ("RightShift",   344),
+        ("RightControl", 345),
+        ("RightAlt",     346),
+        ("Comma",        44),
+        ("Period",       46)
+    ];
+
+    fn is_letter_or_digit(code: i32) -> bool {
+        (code >= '0' as i32 && code <= '9' as i32) || (code >= 'A' as i32 && code <= 'Z' as i32)
+    }
+
+    pub fn from_name(name: &str) -> Option<i32> {
+        if name.len() == 1 {
+            let code = name.as_bytes()[0] as i32;
+            if is_letter_or_digit(code) { return Some(code); }
+        }
+        NAMED.iter().find(|&&(n, _)| n == name).map(|&(_, code)| code)
+    }
+
+    pub fn name(code: i32) -> Option<String> {
+        if is_letter_or_digit(code) {
+            return Some(String::from_char(1, code as u8 as char));
+        }
+        NAMED.iter().find(|&&(_, c)| c == code).map(|&(n, _)| n.to_string())
+    }
+}
+
+#[test]
+fn key_names_go_both_ways() {
+    assert_eq!(key::from_name("W"), Some(key::W));
+    assert_eq!(key::from_name("Up"), Some(key::UP));
+    assert_eq!(key::from_name("w"), None);
+    assert_eq!(key::name(key::LEFT), Some("Left".to_string()));
+    assert_eq!(key::name(key::D), Some("D".to_string()));
+}
+
+// NOTE button numbers are GLFW's too.
+pub mod mouse {
+    pub const LEFT:   i32 = 0;
+    pub const RIGHT:  i32 = 1;
+    pub const MIDDLE: i32 = 2;
+    // GLFW has buttons 0 through 7.
+    pub const LAST:   i32 = 7;
+    pub const BUTTON_COUNT: uint = 8;
+
+    // The rest are named by their number, counting from 1 like GLFW's
+    // names do: Mouse4 to Mouse8.
+    static NAMED: [(&'static str, i32), ..3] = [
+        ("MouseLeft",   LEFT),
+        ("MouseRight",  RIGHT),
+        ("MouseMiddle", MIDDLE)
+    ];
+
+    pub fn from_name(name: &str) -> Option<i32> {
+        match NAMED.iter().find(|&&(n, _)| n == name) {
+            Some(&(_, button)) => return Some(button),
+            None => {}
+        }
+        if !name.starts_with("Mouse") { return None; }
+        match from_str::<i32>(name.slice_from("Mouse".len())) {
+            Some(number) if number > MIDDLE + 1 && number <= LAST + 1 => Some(number - 1),
+            _ => None
+        }
+    }
+
+    pub fn name(button: i32) -> String {
+        match NAMED.iter().find(|&&(_, b)| b == button) {
+            Some(&(n, _)) => n.to_string(),
+            None          => format!("Mouse{}", button + 1)
+        }
+    }
+}
+
+// Gamepad buttons are whatever numbers the joystick reports them as, since
+// they're laid out differently on every pad: Pad0, Pad1...
+// Each axis also works as two buttons, one for pushing it each way: Axis0-
+// and Axis0+.
+pub mod gamepad {
+    fn number_after(prefix: &str, name: &str) -> Option<i32> {
+        if !name.starts_with(prefix) { return None; }
+        match from_str::<i32>(name.slice_from(prefix.len())) {
+            Some(number) if number >= 0 => Some(number),
+            _ => None
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<i32> {
+        number_after("Pad", name)
+    }
+
+    pub fn name(button: i32) -> String {
+        format!("Pad{}", button)
+    }
+
+    // Axis buttons are numbered two per axis, negative then positive.
+    pub fn axis_button(axis: uint, positive: bool) -> i32 {
+        (axis * 2) as i32 + if positive { 1 } else { 0 }
+    }
+
+    pub fn axis_from_name(name: &str) -> Option<i32> {
+        let positive = if name.ends_with("+") {
+            true
+        } else if name.ends_with("-") {
+            false
+        } else {
+            return None;
+        };
+        let axis = name.slice_to(name.len() - 1);
+        number_after("Axis", axis).map(|axis| axis_button(axis as uint, positive))
+    }
+
+    pub fn axis_name(button: i32) -> String {
+        format!("Axis{}{}", button / 2, if button % 2 == 1 { "+" } else { "-" })
+    }
+}
+
+#[test]
+fn button_names_go_both_ways() {
+    assert_eq!(mouse::from_name("MouseLeft"), Some(mouse::LEFT));
+    assert_eq!(mouse::from_name("Mouse5"), Some(4));
+    assert_eq!(mouse::from_name("Mouse1"), None);
+    assert_eq!(mouse::name(4), "Mouse5".to_string());
+    assert_eq!(gamepad::from_name("Pad3"), Some(3));
+    assert_eq!(gamepad::from_name("Pad"), None);
+    assert_eq!(gamepad::axis_from_name("Axis1-"), Some(gamepad::axis_button(1, false)));
+    assert_eq!(gamepad::axis_name(gamepad::axis_button(3, true)), "Axis3+".to_string());
+    assert_eq!(gamepad::axis_from_name("Axis1"), None);
+}
+
+// NOTE make sure this matches Device in the host's input.rs.
+#[repr(C)]
+#[deriving(Copy, PartialEq, Show)]
+pub enum Device {
+    Keyboard,
+    Mouse,
+    Gamepad,
+    // The host never sends these; Joystick makes them out of the axes.
+    GamepadAxis
+}
+
+// NOTE make sure this matches MAX_BUTTON_TRANSITIONS in the host's input.rs.
+pub const MAX_BUTTON_TRANSITIONS: uint = 32;
+
+// NOTE make sure this matches ButtonTransition in the host's input.rs.
+#[repr(C)]
+#[deriving(Copy)]
+pub struct ButtonTransition {
+    pub device:  Device,
+    // A key code for the keyboard, otherwise a button number.
+    pub button:  i32,
+    pub pressed: bool,
+    // When it happened, in seconds on the same clock as GameInput::time.
+    pub time:    f64
+}
+
+// NOTE make sure these match the host's input.rs.
+pub const MAX_GAMEPAD_AXES:    uint = 8;
+pub const MAX_GAMEPAD_BUTTONS: uint = 32;
+
+// NOTE make sure this matches GamepadState in the host's input.rs.
+#[repr(C)]
+#[deriving(Copy)]
+pub struct GamepadState {
+    // Whether there's a joystick plugged in at all. Everything else is zero
+    // if there isn't.
+    pub connected:    bool,
+    // Straight from GLFW: -1 to 1, no deadzone. Axes past axis_count are 0.
+    pub axes:         [f32, ..MAX_GAMEPAD_AXES],
+    pub axis_count:   u32,
+    pub buttons:      [bool, ..MAX_GAMEPAD_BUTTONS],
+    pub button_count: u32
+}
+
+// NOTE make sure this matches GameInput in the host's input.rs.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct GameInput {
+    // Every press and release since last frame, from every device, in order.
+    // Key repeats are left out. Gamepad buttons aren't in here; see gamepad.
+    pub button_transitions:      [ButtonTransition, ..MAX_BUTTON_TRANSITIONS],
+    pub button_transition_count: u32,
+
+    // Always the current size. resized is set if it changed this frame.
+    pub window_width:  i32,
+    pub window_height: i32,
+    pub resized:       bool,
+
+    // The window's close button was hit. Nothing closes unless we say so.
+    pub close_requested: bool,
+
+    // Where the cursor is, in pixels from the window's top left. Can be
+    // outside the window.
+    pub cursor_x: f32,
+    pub cursor_y: f32,
+    // How far the scroll wheel moved since last frame.
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+
+    // When this input was read, in seconds since some arbitrary point.
+    pub time: f64,
+
+    // The first joystick plugged in, as of this frame.
+    pub gamepad: GamepadState
+}
+
+impl GameInput {
+    pub fn button_transitions(&self) -> &[ButtonTransition] {
+        self.button_transitions.slice_to(self.button_transition_count as uint)
+    }
+}
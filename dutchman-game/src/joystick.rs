@@ -0,0 +1,148 @@
+// Turns the raw joystick state the host sends each frame into button
+// presses for Bindings and an analog stick for Actions.
+
+use cgmath::{Vector, Vector2, EuclideanVector};
+use std::num::Float;
+
+use actions::Actions;
+use bindings::Bindings;
+use input::{Device, GamepadState, MAX_GAMEPAD_AXES, MAX_GAMEPAD_BUTTONS, gamepad};
+
+// Sticks rarely rest at exactly zero, so anything this close to the middle
+// counts as the middle.
+pub static DEADZONE: f32 = 0.2;
+// How far an axis has to be pushed to press its Axis0-/Axis0+ button.
+pub static AXIS_PRESS: f32 = 0.5;
+
+// NOTE GLFW doesn't say which axes are which. These are the left stick on
+// most pads.
+pub static STICK_X_AXIS: uint = 0;
+pub static STICK_Y_AXIS: uint = 1;
+
+// Wherever the joystick state comes from. The host's GamepadState in the
+// game proper; tests make up their own.
+pub trait JoystickSource {
+    fn connected(&self) -> bool;
+    // -1 to 1, or 0 for an axis it doesn't have.
+    fn axis(&self, index: uint) -> f32;
+    fn button(&self, index: uint) -> bool;
+}
+
+impl JoystickSource for GamepadState {
+    fn connected(&self) -> bool { self.connected }
+
+    fn axis(&self, index: uint) -> f32 {
+        if index < self.axis_count as uint { self.axes[index] } else { 0.0 }
+    }
+
+    fn button(&self, index: uint) -> bool {
+        index < self.button_count as uint && self.buttons[index]
+    }
+}
+
+// What the joystick was doing last frame, so we know what changed.
+// NOTE all zeroes is a valid Joystick with nothing held.
+pub struct Joystick {
+    pub buttons: [bool, ..MAX_GAMEPAD_BUTTONS],
+    // Per axis: pushed the negative way, pushed the positive way.
+    pub axis_presses: [[bool, ..2], ..MAX_GAMEPAD_AXES]
+}
+
+impl Joystick {
+    pub fn new() -> Joystick {
+        Joystick {
+            buttons:      [false, ..MAX_GAMEPAD_BUTTONS],
+            axis_presses: [[false, ..2], ..MAX_GAMEPAD_AXES]
+        }
+    }
+}
+
+// Zero inside the deadzone, then scaled so it goes smoothly from 0 at the
+// deadzone's edge to 1 at the rim.
+pub fn apply_deadzone(stick: Vector2<f32>) -> Vector2<f32> {
+    let length = stick.length();
+    if length <= DEADZONE {
+        return Vector2::new(0.0, 0.0);
+    }
+    let scaled = (length.min(1.0) - DEADZONE) / (1.0 - DEADZONE);
+    stick.mul_s(scaled / length)
+}
+
+// Presses and releases whatever changed since last frame, through the
+// bindings like any other button. An unplugged joystick lets go of
+// everything.
+// NOTE joysticks only get polled once a frame, so everything is stamped with
+// the time they were polled.
+pub fn update<J: JoystickSource>(joystick: &mut Joystick, source: &J, time: f64,
+                                 bindings: &mut Bindings, actions: &mut Actions) {
+    let connected = source.connected();
+
+    for (index, was_down) in joystick.buttons.iter_mut().enumerate() {
+        let down = connected && source.button(index);
+        if down != *was_down {
+            bindings.apply(actions, Device::Gamepad, index as i32, down, time);
+            *was_down = down;
+        }
+    }
+
+    for (index, presses) in joystick.axis_presses.iter_mut().enumerate() {
+        let value = if connected { source.axis(index) } else { 0.0 };
+        for (positive, was_down) in [false, true].iter().zip(presses.iter_mut()) {
+            let down = if *positive { value >= AXIS_PRESS } else { value <= -AXIS_PRESS };
+            if down != *was_down {
+                bindings.apply(actions, Device::GamepadAxis,
+                               gamepad::axis_button(index, *positive), down, time);
+                *was_down = down;
+            }
+        }
+    }
+
+    let stick = if connected {
+        // NOTE GLFW has y going down.
+        apply_deadzone(Vector2::new(source.axis(STICK_X_AXIS), -source.axis(STICK_Y_AXIS)))
+    } else {
+        Vector2::new(0.0, 0.0)
+    };
+    actions.set_stick(stick);
+}
+
+#[cfg(test)]
+struct FakeJoystick {
+    connected: bool,
+    axes:      Vec<f32>,
+    buttons:   Vec<bool>
+}
+
+#[cfg(test)]
+impl JoystickSource for FakeJoystick {
+    fn connected(&self) -> bool { self.connected }
+    fn axis(&self, index: uint) -> f32 { self.axes.as_slice().get(index).map_or(0.0, |a| *a) }
+    fn button(&self, index: uint) -> bool { self.buttons.as_slice().get(index).map_or(false, |b| *b) }
+}
+
+#[test]
+fn the_stick_moves_the_player() {
+    use actions::Action;
+
+    let mut joystick = Joystick::new();
+    let mut bindings = Bindings::default();
+    let mut actions  = Actions::new();
+    let mut fake = FakeJoystick { connected: true, axes: vec![-0.9, 0.1], buttons: vec![true] };
+
+    update(&mut joystick, &fake, 0.0, &mut bindings, &mut actions);
+    assert!(actions.down(Action::MoveLeft));
+    assert!(actions.up(Action::MoveUp));
+    assert!(actions.down(Action::Interact));
+    let movement = actions.movement();
+    assert!(movement.x < -0.8 && movement.x > -1.0);
+
+    // Barely touching it does nothing.
+    fake.axes = vec![0.1, -0.1];
+    update(&mut joystick, &fake, 0.0, &mut bindings, &mut actions);
+    assert!(actions.up(Action::MoveLeft));
+    assert_eq!(actions.movement(), Vector2::new(0.0, 0.0));
+
+    fake.connected = false;
+    update(&mut joystick, &fake, 0.0, &mut bindings, &mut actions);
+    assert!(actions.up(Action::Interact));
+}
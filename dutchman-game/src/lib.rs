@@ -2,31 +2,81 @@
 extern crate core;
 extern crate libc;
 
-extern crate glfw;
 extern crate gl;
 extern crate cgmath;
 
-use glfw::{Context, Action, Key};
-
 use render::shader;
-use render::texture;
-use render::texture::{Texture, Texcoords, Frame};
+use render::texture::{Texcoords, AtlasBuilder};
+use animation::{Animator, ANIMATOR_LAYOUT, Clip, find_clip};
 use render::sprite::*;
-use controls::{Controls};
+use actions::{Action, Actions, ACTIONS, ACTION_COUNT};
+use bindings::Bindings;
+use joystick::Joystick;
+use mouse::Mouse;
+use input::{GameInput, Device, key};
+use platform::PlatformServices;
+use memory::GameMemory;
 use std::mem::{transmute, size_of, size_of_val, zeroed};
 use gl::types::*;
 use libc::c_void;
-use std::ptr;
 use cgmath::*;
 use std::time::duration::Duration;
 use std::num::Float;
 use std::slice;
+use std::str;
+
+// Like println!, but through the host's platform services.
+macro_rules! log(
+    ($($arg:tt)*) => (::platform::log(format!($($arg)*).as_slice()))
+);
+
+// Declares a struct along with a string describing its fields. The string
+// gets hashed into the LayoutDescriptor so the host can tell when a reload
+// would reinterpret its memory as something else. Also generates
+// save_fields/restore_fields for migrating across such a reload, and
+// dump_fields for printing it.
+// NOTE every field needs a trailing comma for this to match.
+macro_rules! layout(
+    ($layout:ident: $(#[$attr:meta])* pub struct $name:ident { $(pub $field:ident: $typ:ty,)* }) => (
+        $(#[$attr])*
+        pub struct $name { $(pub $field: $typ,)* }
+        pub const $layout: &'static str = stringify!($name { $($field: $typ,)* });
+
+        impl $name {
+            pub fn save_fields(&self, out: &mut ::abi::FieldWriter) {
+                $(out.write(stringify!($field),
+                            ::abi::with_nested(stringify!($typ), &::NESTED_LAYOUTS).as_slice(),
+                            &self.$field);)*
+            }
+
+            pub fn restore_fields(&mut self, saved: &[::abi::SavedField]) {
+                $(::abi::restore_field(saved, stringify!($name), stringify!($field),
+                                       ::abi::with_nested(stringify!($typ), &::NESTED_LAYOUTS).as_slice(),
+                                       &mut self.$field);)*
+            }
+
+            // One "field: value" line per field, for dumping after a headless run.
+            pub fn dump_fields(&self) -> String {
+                let mut out = String::new();
+                $(out.push_str(format!("{}: {:?}\n", stringify!($field), self.$field).as_slice());)*
+                out
+            }
+        }
+    )
+);
 
 pub mod render;
 pub mod asset;
-pub mod controls;
-
-pub type GlfwEvent = Receiver<(f64, glfw::WindowEvent)>;
+pub mod actions;
+pub mod abi;
+pub mod memory;
+pub mod input;
+pub mod platform;
+pub mod bindings;
+pub mod joystick;
+pub mod mouse;
+pub mod animation;
+pub mod aseprite;
 
 macro_rules! gen_buffer(
     ($obj:expr, $buf:expr, $typ:ident, $draw:ident) => (
@@ -66,53 +116,6 @@ macro_rules! stride(
     ($val:expr) => (($val * size_of::<GLfloat>() as i32))
 );
 
-extern "C" {
-    pub fn glfwGetCurrentContext() -> u64;
-    pub fn glfwMakeContextCurrent(window: *mut u8);
-    // NOTE This is a custom function.
-    pub fn glfwCopyDataFrom(data: *const u8);
-    pub fn glfwTestIdent() -> int;
-    pub fn glfwInit() -> bool;
-}
-
-fn set_sprite_attribute(vbo: GLuint) {
-    unsafe {
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        let size_of_sprite = size_of::<SpriteData>() as GLint;
-        assert_eq!(size_of_sprite, 16);
-
-        // == Position ==
-        gl::EnableVertexAttribArray(shader::ATTR_POSITION);
-        gl::VertexAttribPointer(
-            shader::ATTR_POSITION, 2, gl::FLOAT, gl::FALSE as GLboolean,
-            size_of_sprite, as_void!(0)
-        );
-        gl::VertexAttribDivisor(shader::ATTR_POSITION, 1);
-        let mut offset = 2 * size_of::<GLfloat>() as i64;
-        assert_eq!(offset, 8);
-
-        // == Frame ==
-        gl::EnableVertexAttribArray(shader::ATTR_FRAME);
-        gl::VertexAttribIPointer(
-            shader::ATTR_FRAME, 1, gl::INT,
-            size_of_sprite, as_void!(offset)
-        );
-        gl::VertexAttribDivisor(shader::ATTR_FRAME, 1);
-        offset += 1 * size_of::<GLint>() as i64;
-        assert_eq!(offset, 12);
-
-        // == Flipped ==
-        gl::EnableVertexAttribArray(shader::ATTR_FLIPPED);
-        gl::VertexAttribIPointer(
-            shader::ATTR_FLIPPED, 1, gl::INT,
-            size_of_sprite, as_void!(offset)
-        );
-        gl::VertexAttribDivisor(shader::ATTR_FLIPPED, 1);
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-    }
-}
-
 static SQUARE_VERTICES: [GLfloat, ..8] = [
 //    position
      2.0,  2.0, //   1.0, 1.0, // Top right
@@ -131,36 +134,45 @@ static SQUARE_INDICES: [GLuint, ..6] = [
     0.0, 1.0
 */
 
-pub struct Options {
-    pub controls: Controls
+// Which way the player is looking, for picking their clips.
+#[deriving(Copy, PartialEq, Show)]
+pub enum Facing {
+    Down,
+    Side,
+    Up
 }
 
-pub struct GlData {
+// NOTE actions has to come first: it's the only part the host records and
+// plays back (see options_input_size). The rest is the bindings file and
+// what the devices were doing last frame, which have to stay live.
+layout!(OPTIONS_LAYOUT: pub struct Options {
+    pub actions:  Actions,
+    pub bindings: Bindings,
+    pub joystick: Joystick,
+    pub mouse:    Mouse,
+});
+
+layout!(GL_DATA_LAYOUT: pub struct GlData {
     pub vao: GLuint,
     pub square_vbo: GLuint,
     pub square_ebo: GLuint,
 
-    pub shader_prog:         GLuint,
-    pub cam_pos_uniform:     GLint,
-    pub scale_uniform:       GLint,
-    pub sprite_size_uniform: GLint,
-    pub screen_size_uniform: GLint,
-    pub tex_uniform:         GLint,
-    pub frames_uniform:      GLint,
-
-    pub zero_zero_tex: Texture,
-    pub zero_zero_vbo: GLuint,
+    pub shader_prog:          GLuint,
+    pub cam_pos_uniform:      GLint,
+    pub scale_uniform:        GLint,
+    pub texture_size_uniform: GLint,
+    pub screen_size_uniform:  GLint,
+    pub tex_uniform:          GLint,
+    pub frames_uniform:       GLint,
 
-    pub tile_tex: Texture,
-    pub tile_texcoords: [Texcoords, ..14],
-    pub tile_vbo: GLuint,
+    // Every image is packed into one atlas, so everything gets drawn with
+    // this one batch.
+    pub sprites: SpriteBatch,
 
-    pub player_tex: Texture,
-    pub player_texcoords: [Texcoords, ..3],
-    pub player_vbo: GLuint,
-}
+    pub screen_size: Vector2<GLfloat>,
+});
 
-pub struct Game {
+layout!(GAME_LAYOUT: pub struct Game {
     pub initialized: bool,
 
     // NOTE Do whatever you want with this.
@@ -168,75 +180,267 @@ pub struct Game {
 
     pub zero_zero_positions: [SpriteData, ..1],
 
-    pub tile_frame_space: [Frame, ..14], // <- number of frames.
     pub tilemap_position: Vector2<GLfloat>,
     pub tilemap: [[i32, ..10], ..10],
     // pub tile_positions: [SpriteData, ..10*10],
 
-    pub player_frame_space: [Frame, ..3],
     pub player_state: SpriteData,
+    // Plays dutchman.json's tags (see player_clips).
+    pub player_animator: Animator,
+    pub player_facing: Facing,
 
     pub cam_pos: Vector2<GLfloat>,
-}
 
+    // Where things were as of the previous tick, for interpolating.
+    pub prev_player_position: Vector2<GLfloat>,
+    pub prev_cam_pos: Vector2<GLfloat>,
+    // Seconds of frame time that haven't been simulated yet.
+    pub tick_accumulator: f32,
+    // Nothing moves while this is set.
+    pub paused: bool,
+});
+
+// Structs Game holds by value. layout_hash only sees their names, so the
+// layout of each one goes along with any type that mentions it; moving
+// their fields around then counts as a change like any other.
+pub const NESTED_LAYOUTS: [(&'static str, &'static str), ..2] = [
+    ("SpriteData", SPRITE_DATA_LAYOUT),
+    ("Animator",   ANIMATOR_LAYOUT)
+];
+
+// The host checks this against the currently loaded library before it
+// swaps in a new build.
 #[no_mangle]
-pub extern "C" fn load(fresh_load: bool,
-                       glfw_data: *const u8,
-                       window:    &glfw::Window,
-                       game:      &mut Game,
-                       options:   &mut Options,
-                       gldata:   &mut GlData)
-{
-    unsafe {
-        println!("Loading up!");
-        glfwInit();
-        glfwCopyDataFrom(glfw_data); 
-        window.make_current();
-        gl::load_with(|s| window.get_proc_address(s));
+pub extern "C" fn layout_descriptor() -> abi::LayoutDescriptor {
+    abi::LayoutDescriptor {
+        abi_version:  abi::ABI_VERSION,
+        game_size:    size_of::<Game>() as u64,
+        game_hash:    abi::layout_hash(abi::with_nested(GAME_LAYOUT, &NESTED_LAYOUTS).as_slice()),
+        options_size: size_of::<Options>() as u64,
+        options_hash: abi::layout_hash(OPTIONS_LAYOUT),
+        options_input_size: options_input_size(),
+        gl_data_size: size_of::<GlData>() as u64,
+        gl_data_hash: abi::layout_hash(GL_DATA_LAYOUT)
     }
+}
 
-    // === Initialize game state ===
+// Where the bindings start in Options, which is where its input ends.
+fn options_input_size() -> u64 {
+    let options = 0 as *const Options;
+    unsafe { &(*options).bindings as *const Bindings as u64 }
+}
+
+// Called on the OLD library when Game changed layout. Returns how many bytes
+// were written, or 0 if they didn't fit.
+#[no_mangle]
+pub extern "C" fn save_game(game: &Game, buffer: &mut u8, buffer_len: u64) -> u64 {
+    let buffer_ptr: *mut u8 = buffer;
+    let buf = unsafe { slice::from_raw_mut_buf(&buffer_ptr, buffer_len as uint) };
+
+    let mut writer = abi::FieldWriter::new(buf);
+    game.save_fields(&mut writer);
+
+    if writer.overflowed { 0 } else { writer.len as u64 }
+}
+
+// Called on the NEW library with whatever save_game wrote. load(false, ..)
+// gets called right after.
+// NOTE fields that don't make it across get what a fresh start would give
+// them. initialized comes across like any other field, so init_game doesn't
+// run again.
+#[no_mangle]
+pub extern "C" fn restore_game(services: &PlatformServices,
+                               game: &mut Game, data: &u8, data_len: u64) {
+    platform::set(services);
+
+    let data_ptr: *const u8 = data;
+    let saved_data = unsafe { slice::from_raw_buf(&data_ptr, data_len as uint) };
+
+    *game = unsafe { zeroed() };
+    set_game_defaults(game);
+    game.restore_fields(abi::read_fields(saved_data).as_slice());
+}
+
+// Writes a readable dump of Game into the buffer for the host to print.
+// Returns how many bytes were written, or 0 if they didn't fit.
+#[no_mangle]
+pub extern "C" fn dump_game(game: &Game, buffer: &mut u8, buffer_len: u64) -> u64 {
+    let dump = game.dump_fields();
+    if dump.len() as u64 > buffer_len { return 0; }
+
+    let buffer_ptr: *mut u8 = buffer;
+    let buf = unsafe { slice::from_raw_mut_buf(&buffer_ptr, dump.len()) };
+    slice::bytes::copy_memory(buf, dump.as_bytes());
+    dump.len() as u64
+}
+
+// Headless stand-in for load: sets up the game without a window or GL.
+#[no_mangle]
+pub extern "C" fn load_headless(services: &PlatformServices,
+                                game: &mut Game, memory: &mut GameMemory, options: &mut Options) {
+    platform::set(services);
+    log!("Loading up headless!");
     if !game.initialized {
-        game.initialized = true;
+        init_game(game);
+    }
+    load_assets(memory, &aseprite::load("dutchman.json"));
+    options.actions  = Actions::new();
+    options.joystick = Joystick::new();
+    options.mouse    = Mouse::new();
+}
 
-        game.cam_pos = Vector2::new(0.0, 0.0);
+// Headless stand-in for process_input: holds down exactly the actions named
+// in the space separated list, as of time. Returns false if any of them don't
+// exist.
+#[no_mangle]
+pub extern "C" fn set_actions(options: &mut Options, names: *const u8, names_len: u64,
+                              time: f64) -> bool {
+    let names = unsafe { slice::from_raw_buf(&names, names_len as uint) };
+    let names = match str::from_utf8(names) {
+        Some(s) => s,
+        None    => return false
+    };
 
-        game.zero_zero_positions = [
-            SpriteData {
-                position: Vector2::new(0.0, 0.0),
-                frame: -1,
-                flipped: false as GLint
+    let mut held = [false, ..ACTION_COUNT];
+    let mut all_known = true;
+    for name in names.words() {
+        match Action::from_name(name) {
+            Some(action) => held[action.index()] = true,
+            None => {
+                log!("No such action: {}", name);
+                all_known = false;
             }
-        ];
-
-        game.player_state = SpriteData {
-            position: Vector2::new(256.0, 256.0),
-            frame: 1,
-            flipped: true as GLint
-        };
-
-        game.tilemap_position.x = 20.0;
-        game.tilemap_position.y = 128.0;
-        game.tilemap = [
-            [9, 9, 9, 9, 9, 9, 9, 9, 9, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 11, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 11, 2, 2, 9],
-            [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
-            [9, 8, 8, 8, 7, 8, 8, 8, 8, 9]
-        ];
-
-        game.debug_flag = 0;
+        }
+    }
+
+    let actions = &mut options.actions;
+    actions.next_frame(time);
+    for &action in ACTIONS.iter() {
+        actions.set(action, held[action.index()]);
+    }
+    all_known
+}
+
+// Sets up a brand new game. Doesn't touch GL, so tests and headless runs
+// can use it too.
+pub fn init_game(game: &mut Game) {
+    set_game_defaults(game);
+    game.initialized = true;
+}
+
+// Where everything in Game starts out. Doesn't allocate anything, so a
+// migration can put what it restores on top of this.
+fn set_game_defaults(game: &mut Game) {
+    game.cam_pos = Vector2::new(0.0, 0.0);
+
+    game.zero_zero_positions = [
+        SpriteData {
+            position: Vector2::new(0.0, 0.0),
+            frame: 0,
+            flipped: false as GLint
+        }
+    ];
+
+    game.player_state = SpriteData {
+        position: Vector2::new(256.0, 256.0),
+        // The animator picks the real frame on the first tick.
+        frame: 0,
+        flipped: true as GLint
+    };
+    game.player_animator = Animator::new();
+    game.player_facing = Facing::Side;
+    game.prev_player_position = game.player_state.position;
+    game.prev_cam_pos = game.cam_pos;
+    game.tick_accumulator = 0.0;
+
+    game.tilemap_position.x = 20.0;
+    game.tilemap_position.y = 128.0;
+    game.tilemap = [
+        [9, 9, 9, 9, 9, 9, 9, 9, 9, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 11, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 11, 2, 2, 9],
+        [9, 2, 2, 2, 2, 2, 2, 2, 2, 9],
+        [9, 8, 8, 8, 7, 8, 8, 8, 8, 9]
+    ];
+
+    game.debug_flag = 0;
+}
+
+// What's been read out of the asset files. It's the first thing in the
+// assets arena, with the player's clips right after it and then whatever
+// the atlas needs.
+// NOTE snapshots and save states leave that arena alone, so all of this
+// keeps matching the textures that are actually loaded.
+#[allow(missing_copy_implementations)]
+pub struct Assets {
+    pub player_clips: *mut [Clip]
+}
+
+// Starts the assets arena over with the player's clips, so loading again
+// doesn't leave the last load's assets behind.
+fn load_assets(memory: &mut GameMemory, player_sheet: &aseprite::Sheet) {
+    memory.assets.clear();
+    let assets: *mut Assets = memory.assets.push::<Assets>();
+
+    let clips = memory.assets.push_slice::<Clip>(player_sheet.clips.len());
+    for (to, from) in clips.iter_mut().zip(player_sheet.clips.iter()) {
+        *to = *from;
+    }
+    unsafe { (*assets).player_clips = clips; }
+}
+
+// The clips from dutchman.json's tags, or none if nothing's been loaded
+// (like in tests).
+fn player_clips(memory: &GameMemory) -> &[Clip] {
+    if memory.assets.used < size_of::<Assets>() { return &[]; }
+    unsafe { transmute((*(memory.assets.base as *const Assets)).player_clips) }
+}
+
+// The player's tag in dutchman.json for facing that way.
+fn player_clip_name(facing: Facing, walking: bool) -> &'static str {
+    match (facing, walking) {
+        (Facing::Down, false) => "idle_down",
+        (Facing::Side, false) => "idle_side",
+        (Facing::Up,   false) => "idle_up",
+        (Facing::Down, true)  => "walk_down",
+        (Facing::Side, true)  => "walk_side",
+        (Facing::Up,   true)  => "walk_up"
+    }
+}
+
+// NOTE the host makes its GL context current before calling this.
+#[no_mangle]
+pub extern "C" fn load(fresh_load:    bool,
+                       services:      &PlatformServices,
+                       window_width:  i32,
+                       window_height: i32,
+                       game:          &mut Game,
+                       memory:        &mut GameMemory,
+                       options:       &mut Options,
+                       gldata:        &mut GlData)
+{
+    platform::set(services);
+    log!("Loading up!");
+    gl::load_with(|s| platform::get_proc_address(s));
+
+    // === Initialize game state ===
+    if !game.initialized {
+        init_game(game);
     }
 
     // === Initialize GL data if necessary ===
     if fresh_load {
-        // TODO load options from a file
-        options.controls = unsafe { zeroed() };
+        options.actions  = Actions::new();
+        options.bindings = bindings::load();
+        options.joystick = Joystick::new();
+        options.mouse    = Mouse::new();
+
+        gldata.screen_size = Vector2::new(window_width as f32, window_height as f32);
 
         unsafe {
             gl::Enable(gl::BLEND);
@@ -244,13 +448,15 @@ pub extern "C" fn load(fresh_load: bool,
         }
 
         // === Generate textures and the like ===
-        gldata.zero_zero_tex = texture::load_texture("zero-zero.png");
+        let mut atlas = AtlasBuilder::new();
+        atlas.add_image("zero-zero.png");
+        atlas.add("wood-tiles.png", 32, 32, 14);
+        let player_sheet = aseprite::load("dutchman.json");
+        atlas.add_defined(player_sheet.image.as_slice(), player_sheet.frames.as_slice());
+        load_assets(memory, &player_sheet);
 
-        gldata.tile_tex = texture::load_texture("wood-tiles.png");
-        gldata.tile_tex.add_frames(game.tile_frame_space.as_mut_slice(), 32, 32);
-
-        gldata.player_tex = texture::load_texture("dutchman.png");
-        gldata.player_tex.add_frames(game.player_frame_space.as_mut_slice(), 32, 32);
+        let atlas_tex = atlas.build("atlas", &mut memory.assets);
+        let frame_count = atlas_tex.frames().len();
 
         // === Generate global VAO ===
         unsafe {
@@ -265,55 +471,28 @@ pub extern "C" fn load(fresh_load: bool,
             gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE as GLboolean,
                                     stride!(2), as_void!(0));
 
-            // === Generate (by hand) stuff on the screen ===
-            gen_buffer!(gldata.zero_zero_vbo, game.zero_zero_positions, ARRAY_BUFFER, DYNAMIC_DRAW);
-            gen_buffer!(gldata.player_vbo, [game.player_state], ARRAY_BUFFER, DYNAMIC_DRAW);
-
-            gl::GenBuffers(1, &mut gldata.tile_vbo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, gldata.tile_vbo);
-            gl::BufferData(gl::ARRAY_BUFFER,
-                (10 * 10) * size_of::<SpriteData>() as GLsizeiptr,
-                std::ptr::null(),
-                gl::DYNAMIC_DRAW
-            );
-
-            let mut tile_buf = gl::MapBuffer(gl::ARRAY_BUFFER, gl::WRITE_ONLY);
-            let mut tile_sprites = slice::from_raw_mut_buf::<SpriteData>(
-                transmute(&tile_buf),
-                10 * 10
-            );
-            let mut count = 0u;
-            for (y, xs) in game.tilemap.iter().enumerate() {
-                for (x, frame) in xs.iter().enumerate() {
-                    tile_sprites[count] = SpriteData {
-                        position: Vector2::new(x as f32 * 32.0, y as f32 * 32.0) + game.tilemap_position,
-                        frame: *frame,
-                        flipped: false as GLint
-                    };
-                    count += 1;
-                }
-            }
-            gl::UnmapBuffer(gl::ARRAY_BUFFER);
-
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
         }
 
+        // === Generate sprite batch ===
+        gldata.sprites = SpriteBatch::new(atlas_tex, gldata.square_vbo, gldata.square_ebo);
+
         // === Generate shaders ===
-        if !compile_shaders(gldata, game, window) {
+        if !compile_shaders(gldata, game) {
             panic!("Failed to compile or link shaders.");
         }
-        gldata.player_tex.generate_texcoords_buffer(&mut gldata.player_texcoords);
-        gldata.tile_tex.generate_texcoords_buffer(&mut gldata.tile_texcoords);
+        let texcoords_space = memory.assets.push_slice::<Texcoords>(frame_count);
+        gldata.sprites.texture.generate_texcoords_buffer(texcoords_space);
     }
     // if NOT fresh_load:
     else {
-        if !compile_shaders(gldata, game, window) {
-            println!("ERROR COMPILING SHADERS. Shaders not reloaded.");
+        if !compile_shaders(gldata, game) {
+            log!("ERROR COMPILING SHADERS. Shaders not reloaded.");
         }
     }
 }
 
-fn compile_shaders(gl_data: &mut GlData, game: &Game, window: &glfw::Window) -> bool {
+fn compile_shaders(gl_data: &mut GlData, game: &Game) -> bool {
     let existing_program = unsafe {
         if gl::IsProgram(gl_data.shader_prog) == gl::TRUE {
             Some(gl_data.shader_prog)
@@ -327,16 +506,14 @@ fn compile_shaders(gl_data: &mut GlData, game: &Game, window: &glfw::Window) ->
     unsafe { gl::UseProgram(gl_data.shader_prog) }
     gl_data.cam_pos_uniform     = unsafe {     "cam_pos".with_c_str(|c| gl::GetUniformLocation(gl_data.shader_prog, c)) };
     gl_data.scale_uniform       = unsafe {       "scale".with_c_str(|s| gl::GetUniformLocation(gl_data.shader_prog, s)) };
-    gl_data.sprite_size_uniform = unsafe { "sprite_size".with_c_str(|s| gl::GetUniformLocation(gl_data.shader_prog, s)) };
+    gl_data.texture_size_uniform = unsafe { "texture_size".with_c_str(|s| gl::GetUniformLocation(gl_data.shader_prog, s)) };
     gl_data.screen_size_uniform = unsafe { "screen_size".with_c_str(|s| gl::GetUniformLocation(gl_data.shader_prog, s)) };
     gl_data.tex_uniform         = unsafe {         "tex".with_c_str(|t| gl::GetUniformLocation(gl_data.shader_prog, t)) };
     gl_data.frames_uniform      = unsafe {      "frames".with_c_str(|f| gl::GetUniformLocation(gl_data.shader_prog, f)) };
     unsafe {
         gl::Uniform2f(gl_data.cam_pos_uniform, game.cam_pos.x, game.cam_pos.y);
-        gl::Uniform1f(gl_data.scale_uniform, 2.0);
-        match window.get_size() {
-            (width, height) => gl::Uniform2f(gl_data.screen_size_uniform, width as f32, height as f32)
-        }
+        gl::Uniform1f(gl_data.scale_uniform, CAMERA_SCALE);
+        gl::Uniform2f(gl_data.screen_size_uniform, gl_data.screen_size.x, gl_data.screen_size.y);
     }
 
     match existing_program {
@@ -347,6 +524,14 @@ fn compile_shaders(gl_data: &mut GlData, game: &Game, window: &glfw::Window) ->
     true
 }
 
+// How many screen pixels one world pixel takes up.
+pub static CAMERA_SCALE: f32 = 2.0;
+
+static TICK_SECONDS: f32 = 1.0 / 60.0;
+// NOTE after a really long frame (like sitting at a breakpoint) we drop the
+// time we can't catch up on instead of spiralling.
+static MAX_TICKS_PER_FRAME: uint = 10;
+
 // NOTE A negative amount will cause us to go backwards (no duh, right).
 fn towards(start: f32, target: f32, amount: f32) -> f32 {
     let mut value = start;
@@ -377,103 +562,83 @@ fn pos_to_tile_index(position: Vector2<f32>, tilemap_position: Vector2<f32>) ->
     Vector2::new(float_tile_pos.x.floor() as i32, float_tile_pos.y.floor() as i32)
 }
 
+// Everything that changes Actions happens in here, so that the host can
+// record Options after this and play it back over what this did. It still
+// gets called during playback, for resizing and quitting.
+// Returns true when the game wants to quit.
 #[no_mangle]
-pub extern "C" fn update_and_render(
-        game:    &mut Game,
+pub extern "C" fn process_input(
         options: &mut Options,
         gl_data: &mut GlData,
-        delta:   &Duration,
-        glfw:    &glfw::Glfw,
-        window:  &glfw::Window,
-        event:   &GlfwEvent)
+        input:   &GameInput) -> bool
 {
-    glfw.poll_events();
-
-    // TODO testing delta
-    // game.debug_flag += delta.num_milliseconds() as int;
-    // if game.debug_flag >= 1000 {
-    //     game.player_state.frame = match game.player_state.frame {
-    //         2 => 0,
-    //         _ => game.player_state.frame + 1
-    //     };
-    //     game.debug_flag = 0;
-    // }
-
-    let tile_tex      = &gl_data.tile_tex;
-    let zero_zero_tex = &gl_data.zero_zero_tex;
-    let player_tex    = &gl_data.player_tex;
-
-    let mut controls = &mut options.controls;
-    for control in controls.iter_mut() {
-        control.last_frame = control.this_frame;
-    }
-
-    for (_, event) in glfw::flush_messages(event) {
-        match event {
-            glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-                window.set_should_close(true)
-            }
-
-            glfw::WindowEvent::Key(key, _, action, _) => {
-                let control = match key {
-                    Key::W  => &mut controls.up,
-                    Key::Up => &mut controls.up,
+    let mut quit = input.close_requested;
 
-                    Key::S    => &mut controls.down,
-                    Key::Down => &mut controls.down,
+    options.actions.next_frame(input.time);
 
-                    Key::A    => &mut controls.left,
-                    Key::Left => &mut controls.left,
+    bindings::reload_if_edited(&mut options.bindings, &mut options.actions);
 
-                    Key::D     => &mut controls.right,
-                    Key::Right => &mut controls.right,
-
-                    Key::B => &mut controls.debug,
-
-                    _ => break
-                };
-
-                match action {
-                    Action::Press => control.this_frame = true,
-                    Action::Release => control.this_frame = false,
-                    _ => {}
-                }
-            }
+    for transition in input.button_transitions().iter() {
+        // NOTE Escape always quits, whatever it's bound to.
+        if transition.device == Device::Keyboard && transition.button == key::ESCAPE {
+            if transition.pressed { quit = true; }
+            continue;
+        }
+        options.bindings.apply(&mut options.actions, transition.device,
+                               transition.button, transition.pressed, transition.time);
+    }
+    joystick::update(&mut options.joystick, &input.gamepad, input.time,
+                     &mut options.bindings, &mut options.actions);
+    options.mouse.read(input);
 
-            glfw::WindowEvent::Size(width, height) => unsafe {
-                println!("screen is now {} x {}", width, height);
-                gl::Viewport(0, 0, width, height);
-                gl::Uniform2f(gl_data.screen_size_uniform, width as f32, height as f32);
-            },
+    bindings::save_if_changed(&mut options.bindings);
 
-            _ => {}
+    if input.resized {
+        let (width, height) = (input.window_width, input.window_height);
+        log!("screen is now {} x {}", width, height);
+        gl_data.screen_size = Vector2::new(width as f32, height as f32);
+        unsafe {
+            gl::Viewport(0, 0, width, height);
+            gl::Uniform2f(gl_data.screen_size_uniform, width as f32, height as f32);
         }
     }
 
+    quit
+}
+
+// Moves everything forward by exactly delta_sec. Only ever called with
+// TICK_SECONDS, so gameplay doesn't depend on the frame rate.
+fn simulate(game: &mut Game, actions: &Actions, clips: &[Clip], delta_sec: f32) {
     // === Reacting to input ===
-    let delta_sec = delta.num_microseconds().unwrap() as f32 / 1_000_000.0;
     let mut target_player_pos = game.player_state.position.clone();
 
-    if controls.left.down() {
-        target_player_pos.x -= 100.0 * delta_sec;
-        game.player_state.frame = 1;
+    // NOTE the stick gives anything from -1 to 1 here, the move actions
+    // exactly -1, 0 or 1.
+    let movement = actions.movement();
+    target_player_pos = target_player_pos + movement.mul_s(100.0 * delta_sec);
+
+    // === Animating ===
+    // Up and down win over sideways. Standing still keeps facing the same way.
+    if movement.y != 0.0 {
+        game.player_facing = if movement.y > 0.0 { Facing::Up } else { Facing::Down };
         game.player_state.flipped = false as GLint;
+    } else if movement.x != 0.0 {
+        game.player_facing = Facing::Side;
+        game.player_state.flipped = (movement.x > 0.0) as GLint;
     }
-    if controls.right.down() {
-        target_player_pos.x += 100.0 * delta_sec;
-        game.player_state.frame = 1;
-        game.player_state.flipped = true as GLint;
-    }
-    if controls.up.down() {
-        target_player_pos.y += 100.0 * delta_sec;
-        game.player_state.frame = 2;
-        game.player_state.flipped = false as GLint;
+    let walking = movement.x != 0.0 || movement.y != 0.0;
+
+    match find_clip(clips, player_clip_name(game.player_facing, walking)) {
+        Some(clip) => game.player_animator.play(clip),
+        None       => {}
     }
-    if controls.down.down() {
-        target_player_pos.y -= 100.0 * delta_sec;
-        game.player_state.frame = 0;
-        game.player_state.flipped = false as GLint;
+    // NOTE the player's clips all loop, so nothing waits for them to finish.
+    game.player_animator.advance(clips, delta_sec);
+    match game.player_animator.frame(clips) {
+        Some(frame) => game.player_state.frame = frame,
+        None        => {}
     }
+
     // Dumb collision
     let center_offset = Vector2::new(16.0, 0.0);
     let target_tile_pos = pos_to_tile(target_player_pos + center_offset,
@@ -521,6 +686,13 @@ pub extern "C" fn update_and_render(
     };
 
     game.player_state.position = target_player_pos - offset;
+
+    // Tiles the player steps on turn into plain floor.
+    let player_tile = pos_to_tile_index(game.player_state.position + center_offset,
+                                        game.tilemap_position);
+    if player_tile.x >= 0 && player_tile.x < 10 && player_tile.y >= 0 && player_tile.y < 10 {
+        game.tilemap[player_tile.y as uint][player_tile.x as uint] = 0;
+    }
     
     // === Updating camera position ===
     game.cam_pos.y = towards(
@@ -531,72 +703,232 @@ pub extern "C" fn update_and_render(
         game.cam_pos.x, game.player_state.position.x,
         ((game.player_state.position.x - game.cam_pos.x).abs() * 10.0) * delta_sec
     );
+}
 
-    // === Updating buffers ===
-    // Player
-    unsafe {
-        gl::BindBuffer(gl::ARRAY_BUFFER, gl_data.player_vbo);
-        gl::BufferSubData(gl::ARRAY_BUFFER, 0,
-                          size_of::<SpriteData>() as i64,
-                          transmute(&game.player_state));
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+fn lerp(from: Vector2<f32>, to: Vector2<f32>, alpha: f32) -> Vector2<f32> {
+    Vector2::new(from.x + (to.x - from.x) * alpha,
+                 from.y + (to.y - from.y) * alpha)
+}
+
+// Where an image's frames start in the atlas. The game only knows frames
+// within each image, so this gets added on when drawing.
+fn first_frame(gl_data: &GlData, image: &str) -> GLint {
+    match gl_data.sprites.texture.first_frame(image) {
+        Some(frame) => frame,
+        None        => panic!("{} isn't in the atlas.", image)
     }
-    // Tilemap
-    unsafe {
-        let player_tile = pos_to_tile_index(
-            game.player_state.position + center_offset, game.tilemap_position
-        );
+}
 
-        if controls.debug.just_down() {
-            println!("tile num: {}", player_tile);
-        }
+// Draws the game alpha of the way from the previous tick to the current one.
+fn draw(game: &Game, gl_data: &mut GlData, memory: &mut GameMemory, alpha: f32) {
+    let arena = &mut memory.transient;
+
+    let zero_zero_frame = first_frame(gl_data, "zero-zero.png");
+    let tile_first_frame = first_frame(gl_data, "wood-tiles.png");
+    // NOTE the image dutchman.json's frames are in.
+    let player_first_frame = first_frame(gl_data, "dutchman.png");
+
+    // === Pushing sprites ===
+    // NOTE back to front.
+    for sprite in game.zero_zero_positions.iter() {
+        gl_data.sprites.push(arena, SpriteData {
+            frame: zero_zero_frame + sprite.frame,
+            .. *sprite
+        });
+    }
 
-        let stepped_on_tile = SpriteData {
-            position: Vector2::new(
-                          player_tile.x as f32 * 32.0, player_tile.y as f32 * 32.0
-                      ) + game.tilemap_position,
-            frame: 0,
-            flipped: false as GLint
-        };
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, gl_data.tile_vbo);
-        let byte_offset = (player_tile.x as uint +  10 * player_tile.y as uint) * size_of::<SpriteData>();
-        gl::BufferSubData(gl::ARRAY_BUFFER, byte_offset as i64,
-                          size_of::<SpriteData>() as i64,
-                          transmute(&stepped_on_tile));
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    for (y, xs) in game.tilemap.iter().enumerate() {
+        for (x, frame) in xs.iter().enumerate() {
+            gl_data.sprites.push(arena, SpriteData {
+                position: Vector2::new(x as f32 * 32.0, y as f32 * 32.0) + game.tilemap_position,
+                frame: tile_first_frame + *frame,
+                flipped: false as GLint
+            });
+        }
     }
 
+    gl_data.sprites.push(arena, SpriteData {
+        position: lerp(game.prev_player_position, game.player_state.position, alpha),
+        frame: player_first_frame + game.player_state.frame,
+        .. game.player_state
+    });
+
     // === Drawing ===
     unsafe {
-        gl::Uniform2f(gl_data.cam_pos_uniform, game.cam_pos.x, game.cam_pos.y);
+        let cam_pos = lerp(game.prev_cam_pos, game.cam_pos, alpha);
+        gl::Uniform2f(gl_data.cam_pos_uniform, cam_pos.x, cam_pos.y);
 
         gl::ClearColor(0.1, 0.1, 0.3, 1.0);
         gl::Clear(gl::COLOR_BUFFER_BIT);
+    }
+
+    gl_data.sprites.flush(gl_data.tex_uniform, gl_data.texture_size_uniform, gl_data.frames_uniform);
 
-        // Draw zero-zero sign
-        let zero_zero_len = game.zero_zero_positions.len();
-        zero_zero_tex.set_full(gl_data.tex_uniform, gl_data.sprite_size_uniform);
-        set_sprite_attribute(gl_data.zero_zero_vbo);
-        gl::DrawElementsInstanced(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null(), zero_zero_len as i32);
-        // Draw tile!!!
-        tile_tex.set(gl_data.tex_uniform, gl_data.sprite_size_uniform, gl_data.frames_uniform, 32.0, 32.0);
-        set_sprite_attribute(gl_data.tile_vbo);
-        gl::DrawElementsInstanced(
-            gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null(), 10 * 10
-        );
-
-        // Draw PLAYER
-        player_tex.set(gl_data.tex_uniform, gl_data.sprite_size_uniform, gl_data.frames_uniform, 32.0, 32.0);
-        set_sprite_attribute(gl_data.player_vbo);
-        gl::DrawElementsInstanced(
-            gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null(), 1
-        );
-
-        check_error!();
+    unsafe { check_error!(); }
+}
+
+// Runs however many fixed ticks fit in delta. Touches no GL or GLFW, so it
+// can run without a window.
+#[no_mangle]
+pub extern "C" fn update(
+        game:    &mut Game,
+        memory:  &mut GameMemory,
+        options: &Options,
+        delta:   &Duration)
+{
+    memory.transient.clear();
+
+    let actions = &options.actions;
+    let clips = player_clips(memory);
+
+    // NOTE one-shot actions go by pressed_during_frame, so taps shorter than
+    // a frame still count.
+    if actions.pressed_during_frame(Action::Pause) {
+        game.paused = !game.paused;
+        log!("{}", if game.paused { "Paused" } else { "Unpaused" });
+    }
+
+    // === Fixed timestep ===
+    if !game.paused {
+        game.tick_accumulator += delta.num_microseconds().unwrap() as f32 / 1_000_000.0;
+    }
+
+    let mut ticks = 0u;
+    while game.tick_accumulator >= TICK_SECONDS {
+        game.prev_player_position = game.player_state.position;
+        game.prev_cam_pos         = game.cam_pos;
+
+        simulate(game, actions, clips, TICK_SECONDS);
+        game.tick_accumulator -= TICK_SECONDS;
+
+        ticks += 1;
+        if ticks >= MAX_TICKS_PER_FRAME {
+            game.tick_accumulator = 0.0;
+            break;
+        }
+    }
+
+    if actions.pressed_during_frame(Action::Debug) {
+        let center_offset = Vector2::new(16.0, 0.0);
+        log!("tile num: {}", pos_to_tile_index(
+            game.player_state.position + center_offset, game.tilemap_position
+        ));
+
+        let mouse = &options.mouse;
+        if mouse.in_window {
+            let cam_pos = drawn_cam_pos(game);
+            log!("under the cursor: {} (tile {})", mouse.world_position(cam_pos),
+                 mouse.tile_index(cam_pos, game.tilemap_position));
+        }
+    }
+}
+
+// Where the camera will be drawn as of the last update, which is what the
+// player sees and points at.
+fn drawn_cam_pos(game: &Game) -> Vector2<f32> {
+    lerp(game.prev_cam_pos, game.cam_pos, game.tick_accumulator / TICK_SECONDS)
+}
+
+// Draws the game as of the last update. The host swaps buffers after.
+// NOTE sprites go in the transient arena, so this has to come after update
+// in a frame.
+#[no_mangle]
+pub extern "C" fn render(game: &Game, gl_data: &mut GlData, memory: &mut GameMemory) {
+    draw(game, gl_data, memory, game.tick_accumulator / TICK_SECONDS);
+}
+
+#[test]
+fn walking_left_stops_at_the_wall() {
+    let mut game: Box<Game> = box unsafe { zeroed() };
+    let mut options: Options = unsafe { zeroed() };
+    let mut storage = Vec::from_elem(64 * 1024, 0u8);
+    let mut memory = GameMemory {
+        permanent: memory::Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 },
+        transient: memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 },
+        assets:    memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 }
+    };
+    init_game(&mut *game);
+
+    options.actions.set(Action::MoveLeft, true);
+    for _ in range(0u, 300) {
+        update(&mut *game, &mut memory, &options, &Duration::microseconds(16667));
     }
 
-    window.swap_buffers();
+    // The left wall is the first column of tiles.
+    let center_x = game.player_state.position.x + 16.0;
+    assert!(center_x >= game.tilemap_position.x + 32.0);
+    assert!(game.player_state.position.x < 256.0);
+}
+
+#[test]
+fn a_tap_within_one_frame_still_pauses() {
+    platform::set(&platform::TEST_SERVICES);
+    let mut game: Box<Game> = box unsafe { zeroed() };
+    let mut options: Options = unsafe { zeroed() };
+    let mut storage = Vec::from_elem(1024, 0u8);
+    let mut memory = GameMemory {
+        permanent: memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 },
+        transient: memory::Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 },
+        assets:    memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 }
+    };
+    init_game(&mut *game);
+
+    options.actions.next_frame(1.0);
+    options.actions.set_at(Action::Pause, true,  0.99);
+    options.actions.set_at(Action::Pause, false, 0.995);
+    update(&mut *game, &mut memory, &options, &Duration::microseconds(16667));
+    assert!(game.paused);
+}
+
+#[test]
+fn loading_assets_starts_their_arena_over() {
+    use animation::Playback;
+
+    let mut storage = Vec::from_elem(4 * 1024, 0u8);
+    let mut memory = GameMemory {
+        permanent: memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 },
+        transient: memory::Arena { base: storage.as_mut_ptr(), size: 0, used: 0 },
+        assets:    memory::Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 }
+    };
+    assert!(player_clips(&memory).is_empty());
+
+    let sheet = aseprite::Sheet {
+        image:  "dutchman.png".to_string(),
+        frames: Vec::new(),
+        clips:  vec![Clip::new("walk_side", Playback::Loop)]
+    };
+    load_assets(&mut memory, &sheet);
+    let used = memory.assets.used;
+    load_assets(&mut memory, &sheet);
+
+    assert_eq!(memory.assets.used, used);
+    assert_eq!(player_clips(&memory).len(), 1);
+    assert_eq!(player_clips(&memory)[0].name(), "walk_side");
+}
+
+#[test]
+fn fields_missing_from_a_migration_get_their_defaults() {
+    let mut game: Box<Game> = box unsafe { zeroed() };
+    // As if every field were new.
+    let nothing = 0u8;
+    restore_game(&platform::TEST_SERVICES, &mut *game, &nothing, 0);
+
+    assert_eq!(game.player_state.position, Vector2::new(256.0, 256.0));
+    assert_eq!(game.tilemap[0][0], 9);
+}
+
+#[test]
+fn only_actions_are_recorded() {
+    let input_size = options_input_size() as uint;
+    assert!(input_size >= size_of::<Actions>());
+    assert!(input_size < size_of::<Options>());
+}
+
+#[test]
+fn structs_fit_in_host_memory() {
+    assert!(size_of::<Game>()    <= abi::GAME_MEMORY_SIZE);
+    assert!(size_of::<Options>() <= abi::OPTIONS_MEMORY_SIZE);
+    assert!(size_of::<GlData>()  <= abi::GL_MEMORY_SIZE);
 }
 
 #[test]
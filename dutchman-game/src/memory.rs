@@ -1 +1,96 @@
+extern crate core;
 
+use std::mem::{transmute, size_of, min_align_of};
+use std::ptr;
+use std::raw;
+
+// A block of host-owned memory that we bump-allocate out of. Since the host
+// holds onto the Arena structs themselves too, everything in here (and how
+// much of it is used) survives a reload.
+// NOTE make sure this matches Arena in the host's main.rs.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct Arena {
+    pub base: *mut u8,
+    pub size: uint,
+    pub used: uint
+}
+
+// NOTE make sure this matches GameMemory in the host's main.rs.
+#[repr(C)]
+pub struct GameMemory {
+    // Lives as long as the game does. Maps, entity lists and the like.
+    pub permanent: Arena,
+    // Cleared at the start of every frame.
+    pub transient: Arena,
+    // Whatever a fresh load read out of the asset files. Snapshots and save
+    // states leave it alone, since it has to match what's on the GPU.
+    pub assets: Arena
+}
+
+impl Arena {
+    pub fn remaining(&self) -> uint {
+        self.size - self.used
+    }
+
+    // Forget everything that's been pushed.
+    pub fn clear(&mut self) {
+        self.used = 0;
+    }
+
+    // Returns None if there's not enough room left. The memory is zeroed.
+    pub fn try_push_slice<T>(&mut self, count: uint) -> Option<&mut [T]> {
+        let align = min_align_of::<T>();
+        let bytes = size_of::<T>() * count;
+
+        let start   = self.base as uint + self.used;
+        let padding = (align - start % align) % align;
+
+        if padding + bytes > self.remaining() {
+            return None;
+        }
+        self.used += padding + bytes;
+
+        unsafe {
+            let data = (start + padding) as *mut T;
+            ptr::set_memory(data, 0, count);
+            Some(transmute(raw::Slice { data: data as *const T, len: count }))
+        }
+    }
+
+    pub fn push_slice<T>(&mut self, count: uint) -> &mut [T] {
+        let (used, size) = (self.used, self.size);
+        match self.try_push_slice(count) {
+            Some(slice) => slice,
+            None => panic!(
+                "Arena out of memory! Asked for {} x {} bytes with {}/{} used.",
+                count, size_of::<T>(), used, size
+            )
+        }
+    }
+
+    pub fn push<T>(&mut self) -> &mut T {
+        &mut self.push_slice::<T>(1)[0]
+    }
+}
+
+#[test]
+fn arena_aligns_and_runs_out() {
+    let mut storage = [0xFFu8, ..64];
+    let mut arena = Arena { base: &mut storage[0], size: 64, used: 0 };
+
+    arena.push::<u8>();
+    {
+        let int = arena.push::<u32>();
+        assert_eq!(*int, 0);
+        assert_eq!(int as *mut u32 as uint % min_align_of::<u32>(), 0);
+    }
+
+    let rest = arena.remaining();
+    assert!(arena.try_push_slice::<u8>(rest + 1).is_none());
+    assert!(arena.try_push_slice::<u8>(rest).is_some());
+    assert_eq!(arena.remaining(), 0);
+
+    arena.clear();
+    assert_eq!(arena.remaining(), 64);
+}
@@ -0,0 +1,97 @@
+// Where the cursor is and what's under it. Mouse buttons go through
+// Bindings like everything else; they're only kept here too for things like
+// an editor that care about the button itself.
+
+use cgmath::Vector2;
+
+use input::{Device, GameInput, mouse};
+use {CAMERA_SCALE, pos_to_tile_index};
+
+pub struct Mouse {
+    // Pixels from the window's top left, y going down like GLFW has it.
+    pub position:  Vector2<f32>,
+    pub in_window: bool,
+    // How far the wheel moved this frame. Positive y is scrolling up.
+    pub scroll:    Vector2<f32>,
+    pub buttons:   [bool, ..mouse::BUTTON_COUNT],
+    // The window's size as of this frame, for working out world positions.
+    pub screen_size: Vector2<f32>
+}
+
+impl Mouse {
+    pub fn new() -> Mouse {
+        Mouse {
+            position:    Vector2::new(0.0, 0.0),
+            in_window:   false,
+            scroll:      Vector2::new(0.0, 0.0),
+            buttons:     [false, ..mouse::BUTTON_COUNT],
+            screen_size: Vector2::new(1.0, 1.0)
+        }
+    }
+
+    pub fn read(&mut self, input: &GameInput) {
+        self.position    = Vector2::new(input.cursor_x, input.cursor_y);
+        self.scroll      = Vector2::new(input.scroll_x, input.scroll_y);
+        self.screen_size = Vector2::new(input.window_width as f32, input.window_height as f32);
+        self.in_window   = self.position.x >= 0.0 && self.position.x < self.screen_size.x &&
+                           self.position.y >= 0.0 && self.position.y < self.screen_size.y;
+
+        for transition in input.button_transitions().iter() {
+            let button = transition.button as uint;
+            if transition.device == Device::Mouse && button < mouse::BUTTON_COUNT {
+                self.buttons[button] = transition.pressed;
+            }
+        }
+    }
+
+    pub fn down(&self, button: i32) -> bool {
+        (button as uint) < mouse::BUTTON_COUNT && self.buttons[button as uint]
+    }
+
+    // cam_pos should be the one that got drawn, since that's what the
+    // player is pointing at.
+    pub fn world_position(&self, cam_pos: Vector2<f32>) -> Vector2<f32> {
+        screen_to_world(self.position, cam_pos, CAMERA_SCALE, self.screen_size)
+    }
+
+    pub fn tile_index(&self, cam_pos: Vector2<f32>, tilemap_position: Vector2<f32>) -> Vector2<i32> {
+        pos_to_tile_index(self.world_position(cam_pos), tilemap_position)
+    }
+}
+
+// NOTE these have to undo what STANDARD_VERTEX does: a world position ends
+// up (position - cam_pos) * 2 * scale / screen_size in GL's -1 to 1, with
+// the camera in the middle of the screen and y going up.
+pub fn screen_to_world(screen_pos: Vector2<f32>, cam_pos: Vector2<f32>,
+                       scale: f32, screen_size: Vector2<f32>) -> Vector2<f32> {
+    let from_center = Vector2::new(screen_pos.x - screen_size.x / 2.0,
+                                   screen_size.y / 2.0 - screen_pos.y);
+    Vector2::new(cam_pos.x + from_center.x / scale, cam_pos.y + from_center.y / scale)
+}
+
+pub fn world_to_screen(world_pos: Vector2<f32>, cam_pos: Vector2<f32>,
+                       scale: f32, screen_size: Vector2<f32>) -> Vector2<f32> {
+    Vector2::new(screen_size.x / 2.0 + (world_pos.x - cam_pos.x) * scale,
+                 screen_size.y / 2.0 - (world_pos.y - cam_pos.y) * scale)
+}
+
+#[test]
+fn picking_undoes_the_camera() {
+    let screen_size = Vector2::new(640.0, 480.0);
+    let cam_pos     = Vector2::new(100.0, 50.0);
+
+    // The middle of the screen is wherever the camera is.
+    assert_eq!(screen_to_world(Vector2::new(320.0, 240.0), cam_pos, 2.0, screen_size), cam_pos);
+    // Up on the screen is up in the world.
+    assert_eq!(screen_to_world(Vector2::new(384.0, 176.0), cam_pos, 2.0, screen_size),
+               Vector2::new(132.0, 82.0));
+
+    let world = Vector2::new(-12.5, 300.0);
+    let screen = world_to_screen(world, cam_pos, 2.0, screen_size);
+    assert_eq!(screen_to_world(screen, cam_pos, 2.0, screen_size), world);
+
+    let mut mouse = Mouse::new();
+    mouse.screen_size = screen_size;
+    mouse.position = world_to_screen(Vector2::new(80.0, 40.0), cam_pos, CAMERA_SCALE, screen_size);
+    assert_eq!(mouse.tile_index(cam_pos, Vector2::new(0.0, 0.0)), Vector2::new(2, 1));
+}
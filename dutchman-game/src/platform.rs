@@ -0,0 +1,178 @@
+// Everything platform specific the game needs, supplied by the host. Going
+// through this instead of std means a test host can hand us in-memory
+// stand-ins.
+
+use libc::{c_void, c_char};
+use std::ptr;
+#[cfg(test)]
+use std::slice;
+
+pub type GetProcAddressFn = extern "C" fn(*const c_char) -> *const c_void;
+
+// NOTE make sure this matches PlatformServices in the host's platform.rs.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct PlatformServices {
+    // Reads the whole file at path into buffer, if it fits. Returns the
+    // file's size either way, or -1 if it can't be read.
+    pub read_file: extern "C" fn(path: *const u8, path_len: u64,
+                                 buffer: *mut u8, buffer_len: u64) -> i64,
+    // Replaces whatever is at path with data. Returns whether it worked.
+    pub write_file: extern "C" fn(path: *const u8, path_len: u64,
+                                  data: *const u8, data_len: u64) -> bool,
+    // When the file at path was last modified, in milliseconds since some
+    // arbitrary point, or -1 if it doesn't exist.
+    pub modified_time: extern "C" fn(path: *const u8, path_len: u64) -> i64,
+    // Writes the path of the named asset into buffer. Returns the path's
+    // length, or 0 if it didn't fit (or there's no such path).
+    pub asset_path: extern "C" fn(name: *const u8, name_len: u64,
+                                  buffer: *mut u8, buffer_len: u64) -> u64,
+    // Same as asset_path, but for files the player can edit (like key
+    // bindings).
+    pub config_path: extern "C" fn(name: *const u8, name_len: u64,
+                                   buffer: *mut u8, buffer_len: u64) -> u64,
+    pub log: extern "C" fn(message: *const u8, message_len: u64),
+    // Nanoseconds since some arbitrary point.
+    pub precise_time_ns: extern "C" fn() -> u64,
+    // GL functions by name, for the host's current context.
+    pub get_proc_address: GetProcAddressFn
+}
+
+static mut SERVICES: *const PlatformServices = 0 as *const PlatformServices;
+
+// NOTE every export the host can call before load has to call this first.
+pub fn set(services: &PlatformServices) {
+    unsafe { SERVICES = services; }
+}
+
+fn services() -> &'static PlatformServices {
+    unsafe {
+        if SERVICES.is_null() {
+            panic!("No platform services! The host has to call load first.");
+        }
+        &*SERVICES
+    }
+}
+
+pub fn log(message: &str) {
+    (services().log)(message.as_ptr(), message.len() as u64)
+}
+
+pub fn read_file(path: &str) -> Option<Vec<u8>> {
+    let read = services().read_file;
+
+    let size = read(path.as_ptr(), path.len() as u64, ptr::null_mut(), 0);
+    if size < 0 { return None; }
+
+    let mut buffer = Vec::from_elem(size as uint, 0u8);
+    // NOTE this can come back different if the file changed in between.
+    if read(path.as_ptr(), path.len() as u64, buffer.as_mut_ptr(), size as u64) != size {
+        return None;
+    }
+    Some(buffer)
+}
+
+pub fn write_file(path: &str, data: &[u8]) -> bool {
+    (services().write_file)(path.as_ptr(), path.len() as u64, data.as_ptr(), data.len() as u64)
+}
+
+pub fn modified_time(path: &str) -> Option<i64> {
+    match (services().modified_time)(path.as_ptr(), path.len() as u64) {
+        -1   => None,
+        time => Some(time)
+    }
+}
+
+type PathFn = extern "C" fn(*const u8, u64, *mut u8, u64) -> u64;
+
+fn get_path(lookup: PathFn, kind: &str, name: &str) -> String {
+    let mut buffer = [0u8, ..1024];
+    let len = lookup(name.as_ptr(), name.len() as u64, buffer.as_mut_ptr(), buffer.len() as u64);
+    if len == 0 {
+        panic!("Couldn't get a path to {} {} (in {} bytes)", kind, name, buffer.len());
+    }
+    String::from_utf8_lossy(buffer.slice_to(len as uint)).into_string()
+}
+
+pub fn asset_path(name: &str) -> String {
+    get_path(services().asset_path, "asset", name)
+}
+
+pub fn config_path(name: &str) -> String {
+    get_path(services().config_path, "config file", name)
+}
+
+pub fn precise_time_ns() -> u64 {
+    (services().precise_time_ns)()
+}
+
+pub fn get_proc_address(name: &str) -> *const c_void {
+    name.with_c_str(|c| (services().get_proc_address)(c))
+}
+
+// === In-memory stand-ins for tests. ===
+#[cfg(test)]
+unsafe fn test_str<'a>(data: &'a *const u8, len: u64) -> &'a str {
+    ::std::str::from_utf8(slice::from_raw_buf(data, len as uint)).unwrap()
+}
+
+// The only file there is, is "test.txt".
+#[cfg(test)]
+extern "C" fn test_read_file(path: *const u8, path_len: u64, buffer: *mut u8, buffer_len: u64) -> i64 {
+    let contents = b"hello";
+    if unsafe { test_str(&path, path_len) } != "test.txt" { return -1; }
+
+    if buffer_len >= contents.len() as u64 {
+        let buf = unsafe { slice::from_raw_mut_buf(&buffer, contents.len()) };
+        slice::bytes::copy_memory(buf, contents);
+    }
+    contents.len() as i64
+}
+
+#[cfg(test)]
+extern "C" fn test_write_file(_: *const u8, _: u64, _: *const u8, _: u64) -> bool { false }
+
+#[cfg(test)]
+extern "C" fn test_modified_time(path: *const u8, path_len: u64) -> i64 {
+    if unsafe { test_str(&path, path_len) } == "test.txt" { 0 } else { -1 }
+}
+
+// Assets and config files are just named after themselves.
+#[cfg(test)]
+extern "C" fn test_asset_path(name: *const u8, name_len: u64, buffer: *mut u8, buffer_len: u64) -> u64 {
+    if name_len > buffer_len { return 0; }
+    let name = unsafe { slice::from_raw_buf(&name, name_len as uint) };
+    let buf  = unsafe { slice::from_raw_mut_buf(&buffer, name.len()) };
+    slice::bytes::copy_memory(buf, name);
+    name_len
+}
+
+#[cfg(test)]
+extern "C" fn test_log(message: *const u8, message_len: u64) {
+    println!("{}", unsafe { test_str(&message, message_len) });
+}
+
+#[cfg(test)]
+extern "C" fn test_precise_time_ns() -> u64 { 0 }
+
+#[cfg(test)]
+extern "C" fn test_get_proc_address(_: *const c_char) -> *const c_void { ptr::null() }
+
+#[cfg(test)]
+pub static TEST_SERVICES: PlatformServices = PlatformServices {
+    read_file:        test_read_file,
+    write_file:       test_write_file,
+    modified_time:    test_modified_time,
+    asset_path:       test_asset_path,
+    config_path:      test_asset_path,
+    log:              test_log,
+    precise_time_ns:  test_precise_time_ns,
+    get_proc_address: test_get_proc_address
+};
+
+#[test]
+fn files_come_from_the_services() {
+    set(&TEST_SERVICES);
+    assert_eq!(read_file(asset_path("test.txt").as_slice()), Some(b"hello".to_vec()));
+    assert_eq!(read_file("missing.txt"), None);
+}
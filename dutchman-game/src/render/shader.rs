@@ -26,7 +26,7 @@ pub static STANDARD_VERTEX: &'static str = "
         uniform vec2[256] frames;
         uniform vec2 screen_size;
         uniform vec2 cam_pos;     // in pixels
-        uniform vec2 sprite_size; // in pixels
+        uniform vec2 texture_size; // in pixels
         uniform float scale;
 
         out vec2 texcoord;
@@ -70,6 +70,12 @@ pub static STANDARD_VERTEX: &'static str = "
 
         void main()
         {
+            // Frames can all be different sizes, so work it out from their
+            // texcoords. Frame -1 is the whole texture.
+            vec2 sprite_size = texture_size;
+            if (frame != -1)
+                sprite_size = (frames[frame * 4] - frames[frame * 4 + 2]) * texture_size;
+
             vec2 pixel_screen_pos = (position - cam_pos) * 2;
             gl_Position = vec4(
                 (vertex_pos * from_pixel(sprite_size) + from_pixel(pixel_screen_pos)) * scale,
@@ -118,7 +124,7 @@ macro_rules! check_log(
                 $on_error!("{} ERROR: {}", $typ, String::from_utf8(buf));
                 false
             } else {
-                println!("I THINK THE {} COMPILED", $typ);
+                log!("I THINK THE {} COMPILED", $typ);
                 true
             }
         }
@@ -143,7 +149,7 @@ pub fn create_program(vert: &str, frag: &str) -> Option<GLuint> {
     let vert_result = check_log!("VERTEX SHADER"
         GetShaderiv | GetShaderInfoLog
         vert_id COMPILE_STATUS
-        println
+        log
     );
     if !vert_result {
         unsafe { gl::DeleteShader(vert_id); }
@@ -154,7 +160,7 @@ pub fn create_program(vert: &str, frag: &str) -> Option<GLuint> {
     let frag_result = check_log!("FRAGMENT SHADER"
         GetShaderiv | GetShaderInfoLog
         vert_id COMPILE_STATUS
-        println
+        log
     );
     if !frag_result {
         unsafe { gl::DeleteShader(vert_id); }
@@ -172,7 +178,7 @@ pub fn create_program(vert: &str, frag: &str) -> Option<GLuint> {
     let link_result = check_log!("SHADER PROGRAM"
         GetProgramiv | GetProgramInfoLog
         program_id LINK_STATUS
-        println
+        log
     );
     if !link_result {
         unsafe { gl::DeleteProgram(program_id); }
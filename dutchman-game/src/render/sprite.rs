@@ -5,52 +5,255 @@ extern crate gl;
 extern crate cgmath;
 
 use std::mem::{transmute, size_of};
+use std::num::UnsignedInt;
+use std::ptr;
+use libc::c_void;
 
 use render::shader;
+use render::texture::Texture;
+use memory::Arena;
 use cgmath::*;
 use gl::types::*;
 
+layout!(SPRITE_DATA_LAYOUT:
 #[deriving(Copy)]
 #[allow(missing_copy_implementations)]
 pub struct SpriteData {
     pub position: Vector2<GLfloat>,
     pub frame: GLint,
-    pub flipped: GLint
-}
+    pub flipped: GLint,
+});
+
+// How many sprites a batch starts out with room for.
+static INITIAL_CAPACITY: uint = 16;
 
+// Every sprite drawn with one texture. Push sprites into it while drawing,
+// then flush to draw them all with one instanced call.
+// The sprites only last until the transient arena gets cleared, so push
+// them again every frame. The VBO grows to fit the most sprites it's ever
+// been flushed with.
 #[allow(missing_copy_implementations)]
-pub struct Sprite {
+pub struct SpriteBatch {
+    // Sprites can be any of its frames, whatever size they are, or frame -1
+    // for the whole thing.
+    pub texture: Texture,
+
+    // Has the square and the per-sprite attributes all set up, so drawing
+    // is just binding it.
+    pub vao: GLuint,
     pub vbo: GLuint,
-    pub buffer_index: uint
+    pub vbo_capacity: uint,
+
+    // This frame's sprites, in the transient arena.
+    sprites:  *mut SpriteData,
+    capacity: uint,
+    count:    uint
 }
 
-impl Sprite {
-    // TODO THIS IS NOT EVEN USED. SPRITE IS NOT EVEN USED.
-    fn set(&self) {
+impl SpriteBatch {
+    // square_vbo and square_ebo are the shared square every sprite is drawn
+    // with.
+    pub fn new(texture: Texture, square_vbo: GLuint, square_ebo: GLuint) -> SpriteBatch {
+        let mut batch = SpriteBatch {
+            texture:      texture,
+            vao:          0,
+            vbo:          0,
+            vbo_capacity: 0,
+            sprites:      ptr::null_mut(),
+            capacity:     0,
+            count:        0
+        };
+
         unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-            let size_of_sprite = size_of::<SpriteData>() as GLint;
-            assert_eq!(size_of_sprite, 16);
-
-            // == Position ==
-            gl::EnableVertexAttribArray(shader::ATTR_POSITION);
-            gl::VertexAttribPointer(
-                shader::ATTR_POSITION, 2, gl::FLOAT, gl::FALSE as GLboolean,
-                size_of_sprite, transmute(0i64)
-            );
-            gl::VertexAttribDivisor(shader::ATTR_POSITION, 1);
-            let offset = 2 * size_of::<GLfloat>() as i64;
-            assert_eq!(offset, 8);
-
-            // == Frame ==
-            gl::EnableVertexAttribArray(shader::ATTR_FRAME);
-            gl::VertexAttribIPointer(
-                shader::ATTR_FRAME, 1, gl::INT,
-                size_of_sprite, transmute(offset)
-            );
-            gl::VertexAttribDivisor(shader::ATTR_FRAME, 1);
+            gl::GenVertexArrays(1, &mut batch.vao);
+            gl::BindVertexArray(batch.vao);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, square_vbo);
+            gl::EnableVertexAttribArray(shader::ATTR_VERTEX_POS);
+            gl::VertexAttribPointer(shader::ATTR_VERTEX_POS, 2, gl::FLOAT, gl::FALSE as GLboolean,
+                                    2 * size_of::<GLfloat>() as GLint, ptr::null());
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, square_ebo);
+
+            gl::GenBuffers(1, &mut batch.vbo);
+            set_sprite_attributes(batch.vbo);
+
+            gl::BindVertexArray(0);
+        }
+        batch
+    }
 
+    pub fn len(&self) -> uint { self.count }
+
+    // The sprite's position is where its frame's pivot ends up.
+    // NOTE the shader flips a sprite by mirroring its texcoords, so the quad
+    // stays put and the picture mirrors about the frame's center. A pivot
+    // only stays on the same spot of the picture when flipped if it's
+    // centered horizontally. Trimmed frames are moved over so they mirror
+    // about the middle of the frame they were trimmed from instead.
+    pub fn push(&mut self, arena: &mut Arena, mut sprite: SpriteData) {
+        if self.count == self.capacity {
+            self.grow(arena);
+        }
+        match self.texture.frames().get(sprite.frame as uint) {
+            Some(frame) if sprite.frame >= 0 => {
+                sprite.position = sprite.position - frame.pivot;
+                if sprite.flipped != 0 {
+                    sprite.position.x += frame.source_width - frame.size.x
+                                       - 2.0 * frame.source_x;
+                }
+            }
+            _ => {}
+        }
+        unsafe { *self.sprites.offset(self.count as int) = sprite; }
+        self.count += 1;
+    }
+
+    // Moves the sprites into a slice twice as big. The old one just sits in
+    // the arena until it's cleared.
+    fn grow(&mut self, arena: &mut Arena) {
+        let new_capacity = if self.capacity == 0 { INITIAL_CAPACITY } else { self.capacity * 2 };
+        let new_sprites = arena.push_slice::<SpriteData>(new_capacity).as_mut_ptr();
+        if self.count > 0 {
+            unsafe {
+                ptr::copy_nonoverlapping_memory(new_sprites, self.sprites as *const _, self.count);
+            }
+        }
+        self.sprites  = new_sprites;
+        self.capacity = new_capacity;
+    }
+
+    // Draws everything pushed since the last flush, and forgets it.
+    pub fn flush(&mut self, sampler_uniform:      GLint,
+                            texture_size_uniform: GLint,
+                            frames_uniform:       GLint) {
+        if self.count == 0 { return; }
+
+        self.texture.set(sampler_uniform, texture_size_uniform, frames_uniform);
+
+        unsafe {
+            let bytes = (self.count * size_of::<SpriteData>()) as GLsizeiptr;
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            if self.count > self.vbo_capacity {
+                self.vbo_capacity = self.count.next_power_of_two();
+                gl::BufferData(gl::ARRAY_BUFFER,
+                               (self.vbo_capacity * size_of::<SpriteData>()) as GLsizeiptr,
+                               ptr::null(), gl::STREAM_DRAW);
+            }
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, bytes, self.sprites as *const c_void);
             gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            gl::BindVertexArray(self.vao);
+            gl::DrawElementsInstanced(gl::TRIANGLES, 6, gl::UNSIGNED_INT, ptr::null(),
+                                      self.count as GLsizei);
+            gl::BindVertexArray(0);
         }
+
+        self.sprites  = ptr::null_mut();
+        self.capacity = 0;
+        self.count    = 0;
+    }
+}
+
+// Points the per-sprite attributes at vbo, for the bound VAO.
+unsafe fn set_sprite_attributes(vbo: GLuint) {
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    let size_of_sprite = size_of::<SpriteData>() as GLint;
+    assert_eq!(size_of_sprite, 16);
+
+    // == Position ==
+    gl::EnableVertexAttribArray(shader::ATTR_POSITION);
+    gl::VertexAttribPointer(
+        shader::ATTR_POSITION, 2, gl::FLOAT, gl::FALSE as GLboolean,
+        size_of_sprite, ptr::null()
+    );
+    gl::VertexAttribDivisor(shader::ATTR_POSITION, 1);
+    let mut offset = 2 * size_of::<GLfloat>() as i64;
+    assert_eq!(offset, 8);
+
+    // == Frame ==
+    gl::EnableVertexAttribArray(shader::ATTR_FRAME);
+    gl::VertexAttribIPointer(
+        shader::ATTR_FRAME, 1, gl::INT,
+        size_of_sprite, transmute(offset)
+    );
+    gl::VertexAttribDivisor(shader::ATTR_FRAME, 1);
+    offset += 1 * size_of::<GLint>() as i64;
+    assert_eq!(offset, 12);
+
+    // == Flipped ==
+    gl::EnableVertexAttribArray(shader::ATTR_FLIPPED);
+    gl::VertexAttribIPointer(
+        shader::ATTR_FLIPPED, 1, gl::INT,
+        size_of_sprite, transmute(offset)
+    );
+    gl::VertexAttribDivisor(shader::ATTR_FLIPPED, 1);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+}
+
+#[test]
+fn batches_grow_in_the_arena() {
+    use std::mem::zeroed;
+
+    let mut storage = Vec::from_elem(4 * 1024, 0u8);
+    let mut arena = Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 };
+    let mut batch = SpriteBatch {
+        texture: unsafe { zeroed() },
+        vao: 0, vbo: 0, vbo_capacity: 0,
+        sprites: ptr::null_mut(), capacity: 0, count: 0
+    };
+
+    for i in range(0, INITIAL_CAPACITY + 1) {
+        batch.push(&mut arena, SpriteData {
+            position: Vector2::new(i as f32, 0.0), frame: i as GLint, flipped: 0
+        });
+    }
+    assert_eq!(batch.len(), INITIAL_CAPACITY + 1);
+    assert_eq!(batch.capacity, INITIAL_CAPACITY * 2);
+    // Everything pushed before growing came along.
+    for i in range(0, INITIAL_CAPACITY + 1) {
+        assert_eq!(unsafe { (*batch.sprites.offset(i as int)).frame }, i as GLint);
+    }
+}
+
+#[test]
+fn flipping_keeps_sprites_in_place() {
+    use std::mem::zeroed;
+    use render::texture::Frame;
+
+    let mut storage = Vec::from_elem(4 * 1024, 0u8);
+    let mut arena = Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 };
+    let mut frames: Vec<Frame> = range(0u, 3).map(|_| unsafe { zeroed() }).collect();
+    for frame in frames.iter_mut() {
+        frame.size         = Vector2::new(32.0, 32.0);
+        frame.source_width = 32.0;
+    }
+    frames[1].pivot = Vector2::new(16.0, 0.0);
+    // 2px trimmed off the left and 10px off the right.
+    frames[2].size     = Vector2::new(20.0, 32.0);
+    frames[2].pivot    = Vector2::new(-2.0, 0.0);
+    frames[2].source_x = 2.0;
+
+    let mut batch = SpriteBatch {
+        texture: unsafe { zeroed() },
+        vao: 0, vbo: 0, vbo_capacity: 0,
+        sprites: ptr::null_mut(), capacity: 0, count: 0
+    };
+    batch.texture.frame_space = frames.as_mut_slice();
+
+    let at = Vector2::new(100.0, 50.0);
+    for &(frame, flipped) in [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0), (2, 1)].iter() {
+        batch.push(&mut arena, SpriteData { position: at, frame: frame, flipped: flipped });
     }
+    let pushed = |i: int| unsafe { (*batch.sprites.offset(i)).position };
+    // A grid frame goes right where it's put, flipped or not.
+    assert_eq!(pushed(0), at);
+    assert_eq!(pushed(1), at);
+    // A centered pivot stays centered either way.
+    assert_eq!(pushed(2), Vector2::new(84.0, 50.0));
+    assert_eq!(pushed(3), Vector2::new(84.0, 50.0));
+    // A trimmed frame ends up where its picture was in the untrimmed frame,
+    // mirrored when flipped.
+    assert_eq!(pushed(4), Vector2::new(102.0, 50.0));
+    assert_eq!(pushed(5), Vector2::new(110.0, 50.0));
 }
@@ -6,9 +6,12 @@ extern crate cgmath;
 
 use cgmath::*;
 use std::mem::{size_of, transmute, uninitialized};
+use std::slice;
+use std::str;
 use self::image::{GenericImage};
 use gl::types::*;
 use render::shader;
+use memory::Arena;
 
 use asset;
 
@@ -21,10 +24,18 @@ pub struct Texcoords {
     pub top_left:     Vector2<GLfloat>
 }
 
-// Represents an animation frame; a square section of a Texture.
+// Represents an animation frame; a rectangular section of a Texture.
 pub struct Frame {
     pub position: Vector2<f32>,
     pub size: Vector2<f32>,
+    // The point on the frame that goes wherever the sprite is positioned, in
+    // pixels from the frame's bottom left. Zero for frames cut from a grid.
+    pub pivot: Vector2<f32>,
+    // For frames trimmed of their empty edges: how wide the frame was
+    // before, and how far in from its left this part was cut. Flipped
+    // sprites mirror about the middle of the untrimmed frame.
+    pub source_width: f32,
+    pub source_x:     f32,
 
     // Texcoords are generated via generate_texcoords.
     pub texcoords: Texcoords
@@ -68,19 +79,12 @@ pub struct Texture {
     pub filename: &'static str,
     pub frame_space: *mut [Frame],
     pub frame_texcoords_size: i64,
-    pub texcoords_space: *mut [Texcoords]
+    pub texcoords_space: *mut [Texcoords],
+    // Which frames came from which image, for atlases. Empty otherwise.
+    pub images: *mut [AtlasImage]
 }
 
 impl Texture {
-    pub fn set_full(&self, sampler_uniform: GLint, sprite_size_uniform: GLint) {
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-            gl::Uniform1i(sampler_uniform, 0);
-            gl::Uniform2f(sprite_size_uniform, self.width as f32, self.height as f32);
-        }
-    }
-
     #[inline]
     pub fn frames_mut(&mut self) -> &mut [Frame] {
         unsafe { transmute(self.frame_space) }
@@ -113,19 +117,25 @@ impl Texture {
         &frames[i]
     }
 
+    // Where the frames from the given image start, if it went into this
+    // texture.
+    pub fn first_frame(&self, filename: &str) -> Option<GLint> {
+        let images: &[AtlasImage] = unsafe { transmute(self.images) };
+        images.iter().find(|i| i.name() == filename).map(|i| i.first_frame as GLint)
+    }
+
     // NOTE this expects generate_texcoords_buffer to have been called
     // if there are frames.
-    pub fn set(&self, sampler_uniform:     GLint,
-                      sprite_size_uniform: GLint,
-                      frames_uniform:      GLint,
-                      width: f32, height: f32) {
+    pub fn set(&self, sampler_uniform:      GLint,
+                      texture_size_uniform: GLint,
+                      frames_uniform:       GLint) {
         unsafe {
             assert!(self.frame_texcoords_size / 8 < shader::FRAME_UNIFORM_MAX);
 
             gl::ActiveTexture(gl::TEXTURE0);
             gl::BindTexture(gl::TEXTURE_2D, self.id);
             gl::Uniform1i(sampler_uniform, 0);
-            gl::Uniform2f(sprite_size_uniform, width as f32, height as f32);
+            gl::Uniform2f(texture_size_uniform, self.width as f32, self.height as f32);
 
             let frames_len = self.frames().len();
 
@@ -158,40 +168,11 @@ impl Texture {
     // Fill the given slice with frames of the given width and height.
     pub fn add_frames(&mut self, space: *mut [Frame], uwidth: uint, uheight: uint) {
         let count = unsafe { (*space).len() };
-        let tex_width  = self.width as f32;
-        let tex_height = self.height as f32;
-        let width  = uwidth as f32;
-        let height = uheight as f32;
+        let region = Placement { x: 0, y: 0, width: self.width as u32, height: self.height as u32 };
+        let (tex_width, tex_height) = (self.width as f32, self.height as f32);
 
         self.frame_space = space;
-        {
-            let mut frames = self.frames_mut();
-
-            let mut current_pos = Vector2::<f32>::new(0.0, tex_height - height);
-
-            for i in range(0u, count) {
-                if current_pos.x + width > tex_width {
-                    current_pos.x = 0.0;
-                    current_pos.y -= height;
-                }
-                if current_pos.y < 0.0 {
-                    panic!(
-                        "Too many frames! Asked for {} {}x{} frames on a {}x{} texture.",
-                        count, width, height, tex_width, tex_height
-                    );
-                }
-
-                let mut frame = Frame {
-                    position:  current_pos,
-                    size:      Vector2::new(width, height),
-                    texcoords: unsafe { uninitialized() }
-                };
-                frame.generate_texcoords(tex_width, tex_height);
-                frames[i] = frame;
-
-                current_pos.x += width;
-            }
-        }
+        cut_grid(self.frames_mut(), &region, uwidth, uheight, tex_width, tex_height);
 
         self.frame_texcoords_size += size_of::<Texcoords>() as i64 * count as i64;
     }
@@ -205,13 +186,189 @@ impl Texture {
     }
 }
 
-// Load a texture from the given filename into the GPU
-// memory, returning a struct holding the OpenGL ID and
-// dimensions.
-pub fn load_texture(filename: &'static str) -> Texture {
-    let img = image::open(&asset::path(filename)).unwrap();
-    let (width, height) = match img.dimensions() { (w, h) => (w as i32, h as i32) };
+// Cuts frames of the given size out of region, left to right and top to
+// bottom, one for each slot in frames.
+fn cut_grid(frames: &mut [Frame], region: &Placement, uwidth: uint, uheight: uint,
+            tex_width: f32, tex_height: f32) {
+    let count  = frames.len();
+    let width  = uwidth as f32;
+    let height = uheight as f32;
+    // NOTE Frame positions have y going up from the bottom of the texture,
+    // where placements have it going down from the top.
+    let left   = region.x as f32;
+    let right  = (region.x + region.width) as f32;
+    let bottom = tex_height - (region.y + region.height) as f32;
+    let top    = tex_height - region.y as f32;
+
+    let mut current_pos = Vector2::<f32>::new(left, top - height);
+
+    for i in range(0u, count) {
+        if current_pos.x + width > right {
+            current_pos.x = left;
+            current_pos.y -= height;
+        }
+        if current_pos.y < bottom {
+            panic!(
+                "Too many frames! Asked for {} {}x{} frames in a {}x{} region.",
+                count, width, height, region.width, region.height
+            );
+        }
+
+        let mut frame = Frame {
+            position:     current_pos,
+            size:         Vector2::new(width, height),
+            pivot:        Vector2::new(0.0, 0.0),
+            source_width: width,
+            source_x:     0.0,
+            texcoords:    unsafe { uninitialized() }
+        };
+        frame.generate_texcoords(tex_width, tex_height);
+        frames[i] = frame;
+
+        current_pos.x += width;
+    }
+}
+
+// Puts each def's frame where it says, relative to region.
+fn cut_defined(frames: &mut [Frame], region: &Placement, defs: &[FrameDef],
+               tex_width: f32, tex_height: f32) {
+    for (frame, def) in frames.iter_mut().zip(defs.iter()) {
+        if def.x + def.width > region.width || def.y + def.height > region.height {
+            panic!("Frame {} ({}x{} at {}, {}) doesn't fit in a {}x{} image.",
+                   def.name, def.width, def.height, def.x, def.y, region.width, region.height);
+        }
+
+        let (width, height) = (def.width as f32, def.height as f32);
+        *frame = Frame {
+            position:     Vector2::new((region.x + def.x) as f32,
+                                       tex_height - (region.y + def.y) as f32 - height),
+            size:         Vector2::new(width, height),
+            pivot:        Vector2::new(def.pivot.x, height - def.pivot.y),
+            source_width: def.source_width as f32,
+            source_x:     def.source_x as f32,
+            texcoords:    unsafe { uninitialized() }
+        };
+        frame.generate_texcoords(tex_width, tex_height);
+    }
+}
+
+// === Frame metadata ===
+
+// One frame out of a metadata file. Unlike Frame, these are how image
+// editors see things: pixels from the image's top left, y going down.
+#[deriving(Clone, PartialEq, Show)]
+pub struct FrameDef {
+    pub name:   String,
+    pub x:      u32,
+    pub y:      u32,
+    pub width:  u32,
+    pub height: u32,
+    // From the frame's top left. Defaults to its bottom left, which is
+    // where sprites cut from a grid are positioned from.
+    pub pivot:  Vector2<f32>,
+    // Like Frame's. The same as width and 0 for frames that weren't
+    // trimmed.
+    pub source_width: u32,
+    pub source_x:     u32
+}
+
+// Reads frames from text like
+//
+//     # name      x   y  width height  pivot x, y (optional)
+//     player_down 0   0  32    48      16 48
+//     coin        32  0  16    16
+//
+// Frames are numbered in the order they're listed.
+pub fn parse_frame_defs(text: &str) -> Result<Vec<FrameDef>, String> {
+    let mut defs: Vec<FrameDef> = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") { continue; }
+
+        let words: Vec<&str> = line.words().collect();
+        if words.len() != 5 && words.len() != 7 {
+            return Err(format!("line {}: expected a name, x, y, width, height and maybe a pivot",
+                               number + 1));
+        }
+        let name = words[0];
+        if defs.iter().any(|d| d.name.as_slice() == name) {
+            return Err(format!("line {}: there's already a frame called {}", number + 1, name));
+        }
+
+        let mut numbers = Vec::new();
+        for word in words.slice_from(1).iter() {
+            match from_str::<u32>(*word) {
+                Some(n) => numbers.push(n),
+                None => return Err(format!("line {}: {} isn't a number of pixels", number + 1, word))
+            }
+        }
+        let pivot = if numbers.len() == 6 {
+            Vector2::new(numbers[4] as f32, numbers[5] as f32)
+        } else {
+            Vector2::new(0.0, numbers[3] as f32)
+        };
+
+        defs.push(FrameDef {
+            name:   name.to_string(),
+            x:      numbers[0],
+            y:      numbers[1],
+            width:  numbers[2],
+            height: numbers[3],
+            pivot:  pivot,
+            source_width: numbers[2],
+            source_x:     0
+        });
+    }
+
+    Ok(defs)
+}
+
+// The metadata file that goes with an image: hero.png has hero.frames.
+fn frame_defs_filename(image: &str) -> String {
+    match image.rfind('.') {
+        Some(dot) => format!("{}.frames", image.slice_to(dot)),
+        None      => format!("{}.frames", image)
+    }
+}
 
+// Reads an image's metadata file out of the assets directory, or None if it
+// doesn't have one.
+pub fn load_frame_defs(image: &str) -> Option<Vec<FrameDef>> {
+    let filename = frame_defs_filename(image);
+    let bytes = match asset::read(filename.as_slice()) {
+        Some(bytes) => bytes,
+        None        => return None
+    };
+    let text = match str::from_utf8(bytes.as_slice()) {
+        Some(text) => text,
+        None => panic!("{} isn't text", filename)
+    };
+    match parse_frame_defs(text) {
+        Ok(defs) => Some(defs),
+        Err(e) => panic!("{}: {}", filename, e)
+    }
+}
+
+// An image decoded into RGBA bytes, top row first.
+struct Pixels {
+    width:  u32,
+    height: u32,
+    data:   Vec<u8>
+}
+
+fn read_pixels(filename: &str) -> Pixels {
+    let bytes = match asset::read(filename) {
+        Some(bytes) => bytes,
+        None => panic!("Couldn't read texture {}", filename)
+    };
+    let img = image::load_from_memory(bytes.as_slice(), image::ImageFormat::PNG).unwrap();
+    let (width, height) = img.dimensions();
+    Pixels { width: width, height: height, data: img.to_rgba().as_slice().to_vec() }
+}
+
+// Sends the pixels to the GPU, returning the new texture's ID.
+fn upload(name: &str, pixels: &Pixels) -> GLuint {
     let mut tex_id: GLuint = 0;
 
     unsafe {
@@ -225,24 +382,330 @@ pub fn load_texture(filename: &'static str) -> Texture {
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
         gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
 
-        let rgba = img.to_rgba();
-        let buf = rgba.as_slice();
-
-        println!("Sending {} to GPU. Width: {} Height: {}", filename, width, height);
+        log!("Sending {} to GPU. Width: {} Height: {}", name, pixels.width, pixels.height);
         gl::TexImage2D(
             gl::TEXTURE_2D, 0, gl::RGBA as i32,
-            width, height, 0, gl::RGBA,
-            gl::UNSIGNED_BYTE, transmute(&buf[0])
+            pixels.width as i32, pixels.height as i32, 0, gl::RGBA,
+            gl::UNSIGNED_BYTE, transmute(&pixels.data[0])
         );
     }
 
+    tex_id
+}
+
+// Load a texture from the given filename into the GPU
+// memory, returning a struct holding the OpenGL ID and
+// dimensions.
+pub fn load_texture(filename: &'static str) -> Texture {
+    let pixels = read_pixels(filename);
+
     Texture {
-        id: tex_id,
-        width: width,
-        height: height,
+        id: upload(filename, &pixels),
+        width: pixels.width as i32,
+        height: pixels.height as i32,
         filename: filename,
         frame_space: &mut [],
         frame_texcoords_size: 0,
-        texcoords_space: &mut []
+        texcoords_space: &mut [],
+        images: &mut []
+    }
+}
+
+// === Atlases ===
+
+// Where a rectangle went, in pixels from the top left.
+#[deriving(Copy, PartialEq, Show)]
+pub struct Placement {
+    pub x:      u32,
+    pub y:      u32,
+    pub width:  u32,
+    pub height: u32
+}
+
+// NOTE longer filenames get cut off.
+pub const MAX_IMAGE_NAME: uint = 32;
+
+// Where one image's frames ended up in an atlas.
+#[deriving(Copy)]
+pub struct AtlasImage {
+    name:     [u8, ..MAX_IMAGE_NAME],
+    name_len: uint,
+
+    pub first_frame: uint,
+    pub frame_count: uint
+}
+
+impl AtlasImage {
+    pub fn new(filename: &str, first_frame: uint, frame_count: uint) -> AtlasImage {
+        let mut image = AtlasImage {
+            name:        [0, ..MAX_IMAGE_NAME],
+            name_len:    0,
+            first_frame: first_frame,
+            frame_count: frame_count
+        };
+        for (to, from) in image.name.iter_mut().zip(filename.bytes()) {
+            *to = from;
+            image.name_len += 1;
+        }
+        image
+    }
+
+    pub fn name(&self) -> &str {
+        str::from_utf8(self.name.slice_to(self.name_len)).unwrap_or("")
+    }
+}
+
+// How many frames one texture can have. The shader's frames uniform has 4
+// texcoords per frame, and Texture::set wants it to have room to spare.
+pub fn max_frames() -> uint {
+    (shader::FRAME_UNIFORM_MAX / 4 - 1) as uint
+}
+
+// Empty pixels around each image, so filtering never picks up a neighbour.
+static ATLAS_PADDING: u32 = 1;
+static ATLAS_MAX_SIZE: u32 = 4096;
+
+// Packs rectangles into rows ("shelves"), tallest first, in a space width
+// wide. Returns where each one went (in the same order as sizes) and how
+// tall the space had to be, or None if something is wider than width.
+pub fn pack_rects(sizes: &[(u32, u32)], width: u32) -> Option<(Vec<Placement>, u32)> {
+    let mut order: Vec<uint> = range(0, sizes.len()).collect();
+    order.sort_by(|&a, &b| {
+        let ((_, height_a), (_, height_b)) = (sizes[a], sizes[b]);
+        height_b.cmp(&height_a)
+    });
+
+    let mut placements = Vec::from_elem(sizes.len(), Placement { x: 0, y: 0, width: 0, height: 0 });
+    let (mut shelf_x, mut shelf_y, mut shelf_height) = (0u32, 0u32, 0u32);
+
+    for &index in order.iter() {
+        let (w, h) = sizes[index];
+        let (padded_w, padded_h) = (w + ATLAS_PADDING, h + ATLAS_PADDING);
+        if padded_w > width { return None; }
+
+        if shelf_x + padded_w > width {
+            shelf_y += shelf_height;
+            shelf_x = 0;
+            shelf_height = 0;
+        }
+        placements[index] = Placement { x: shelf_x, y: shelf_y, width: w, height: h };
+        shelf_x += padded_w;
+        shelf_height = shelf_height.max(padded_h);
+    }
+
+    Some((placements, shelf_y + shelf_height))
+}
+
+// How an image in an atlas gets cut into frames.
+enum Cutting {
+    Whole,
+    Grid(uint, uint),
+    Defined(Vec<FrameDef>)
+}
+
+struct AtlasSource {
+    filename:    String,
+    pixels:      Pixels,
+    cutting:     Cutting,
+    frame_count: uint
+}
+
+// Collects images to pack into one texture, so sprites from all of them can
+// be drawn in one batch. Their frames all end up in one list, in the order
+// the images were added, and the texture's first_frame says where each
+// image's start.
+pub struct AtlasBuilder {
+    sources: Vec<AtlasSource>,
+    frame_count: uint
+}
+
+impl AtlasBuilder {
+    pub fn new() -> AtlasBuilder {
+        AtlasBuilder { sources: Vec::new(), frame_count: 0 }
+    }
+
+    // Adds an image cut into count frames of the given size, like
+    // add_frames. Returns the index of its first frame in the atlas.
+    pub fn add(&mut self, filename: &str,
+               frame_width: uint, frame_height: uint, count: uint) -> uint {
+        self.push(filename, Cutting::Grid(frame_width, frame_height), count)
+    }
+
+    // Adds an image cut up the way its .frames file says, or as a single
+    // frame if it doesn't have one. Returns the index of its first frame.
+    pub fn add_image(&mut self, filename: &str) -> uint {
+        match load_frame_defs(filename) {
+            Some(defs) => self.add_defined(filename, defs.as_slice()),
+            None       => self.push(filename, Cutting::Whole, 1)
+        }
+    }
+
+    // Adds an image cut up the way defs says. Returns the index of its first
+    // frame in the atlas; the rest follow in the order of defs.
+    pub fn add_defined(&mut self, filename: &str, defs: &[FrameDef]) -> uint {
+        self.push(filename, Cutting::Defined(defs.to_vec()), defs.len())
+    }
+
+    fn push(&mut self, filename: &str, cutting: Cutting, count: uint) -> uint {
+        let first_frame = self.frame_count;
+        self.sources.push(AtlasSource {
+            filename:    filename.to_string(),
+            pixels:      read_pixels(filename),
+            cutting:     cutting,
+            frame_count: count
+        });
+        self.frame_count += count;
+        first_frame
+    }
+
+    // Which images the frames came from, for complaining about.
+    fn frame_counts(&self) -> String {
+        let counts: Vec<String> = self.sources.iter()
+            .map(|s| format!("{}: {}", s.filename, s.frame_count)).collect();
+        counts.connect(", ")
+    }
+
+    // Packs everything into one texture. Every image's frames, and which
+    // image they came from, go in the arena.
+    pub fn build(self, name: &'static str, arena: &mut Arena) -> Texture {
+        // NOTE otherwise the assert in set would go off on every render.
+        if self.frame_count > max_frames() {
+            panic!("Atlas {} has {} frames, but the shader only has room for {}. {}",
+                   name, self.frame_count, max_frames(), self.frame_counts());
+        }
+
+        let sizes: Vec<(u32, u32)> = self.sources.iter()
+            .map(|s| (s.pixels.width, s.pixels.height)).collect();
+
+        // Try wider and wider until it's about square.
+        let mut width = 64u32;
+        let mut packed = None;
+        while packed.is_none() {
+            match pack_rects(sizes.as_slice(), width) {
+                Some((placements, height)) if height <= width || width >= ATLAS_MAX_SIZE =>
+                    packed = Some((placements, height)),
+                _ if width >= ATLAS_MAX_SIZE =>
+                    panic!("Atlas {} doesn't fit in {}x{}.", name, ATLAS_MAX_SIZE, ATLAS_MAX_SIZE),
+                _ => width *= 2
+            }
+        }
+        let (placements, height) = packed.unwrap();
+        if height > ATLAS_MAX_SIZE {
+            panic!("Atlas {} doesn't fit in {}x{}.", name, ATLAS_MAX_SIZE, ATLAS_MAX_SIZE);
+        }
+
+        // === Copying the images in ===
+        let mut atlas = Pixels {
+            width:  width,
+            height: height,
+            data:   Vec::from_elem((width * height * 4) as uint, 0u8)
+        };
+        for (source, place) in self.sources.iter().zip(placements.iter()) {
+            let row_bytes = (place.width * 4) as uint;
+            for row in range(0, place.height) {
+                let from = (row * place.width * 4) as uint;
+                let to   = (((place.y + row) * width + place.x) * 4) as uint;
+                slice::bytes::copy_memory(atlas.data.slice_mut(to, to + row_bytes),
+                                          source.pixels.data.slice(from, from + row_bytes));
+            }
+        }
+
+        let frame_space: *mut [Frame]      = arena.push_slice::<Frame>(self.frame_count);
+        let image_space: *mut [AtlasImage] = arena.push_slice::<AtlasImage>(self.sources.len());
+        let mut texture = Texture {
+            id: upload(name, &atlas),
+            width: width as i32,
+            height: height as i32,
+            filename: name,
+            frame_space: frame_space,
+            frame_texcoords_size: size_of::<Texcoords>() as i64 * self.frame_count as i64,
+            texcoords_space: &mut [],
+            images: image_space
+        };
+
+        // === Cutting out frames ===
+        {
+            let images: &mut [AtlasImage] = unsafe { transmute(image_space) };
+            let frames = texture.frames_mut();
+            let mut next_frame = 0u;
+            for ((source, place), image) in self.sources.iter().zip(placements.iter())
+                                                .zip(images.iter_mut()) {
+                *image = AtlasImage::new(source.filename.as_slice(), next_frame, source.frame_count);
+
+                let space = frames.slice_mut(next_frame, next_frame + source.frame_count);
+                let (tex_width, tex_height) = (width as f32, height as f32);
+                match source.cutting {
+                    Cutting::Whole =>
+                        cut_grid(space, place, place.width as uint, place.height as uint,
+                                 tex_width, tex_height),
+                    Cutting::Grid(frame_width, frame_height) =>
+                        cut_grid(space, place, frame_width, frame_height, tex_width, tex_height),
+                    Cutting::Defined(ref defs) =>
+                        cut_defined(space, place, defs.as_slice(), tex_width, tex_height)
+                }
+                log!("{} is frames {} to {} of {}", source.filename,
+                     next_frame, next_frame + source.frame_count - 1, name);
+                next_frame += source.frame_count;
+            }
+        }
+
+        texture
     }
 }
+
+#[test]
+fn rects_pack_without_overlapping() {
+    let sizes = [(200, 50), (256, 32), (192, 96), (16, 16)];
+    let (placements, height) = pack_rects(&sizes, 512).unwrap();
+
+    for (i, a) in placements.iter().enumerate() {
+        assert_eq!((a.width, a.height), sizes[i]);
+        assert!(a.x + a.width <= 512 && a.y + a.height <= height);
+        for b in placements.slice_from(i + 1).iter() {
+            let apart = a.x + a.width <= b.x || b.x + b.width <= a.x ||
+                        a.y + a.height <= b.y || b.y + b.height <= a.y;
+            assert!(apart, "{} overlaps {}", a, b);
+        }
+    }
+    assert!(pack_rects(&sizes, 128).is_none());
+}
+
+#[test]
+fn atlas_images_are_found_by_name() {
+    use std::mem::zeroed;
+
+    let mut images = [AtlasImage::new("zero-zero.png", 0, 1),
+                      AtlasImage::new("wood-tiles.png", 1, 14)];
+    let mut texture: Texture = unsafe { zeroed() };
+    texture.images = images.as_mut_slice();
+
+    assert_eq!(texture.first_frame("wood-tiles.png"), Some(1));
+    assert_eq!(texture.first_frame("dutchman.png"), None);
+}
+
+#[test]
+fn frame_defs_can_be_any_size() {
+    use std::mem::zeroed;
+
+    let defs = parse_frame_defs("
+        # A tall character next to a small item.
+        hero 0  0 32 48  16 48
+        coin 32 0 16 16
+    ").unwrap();
+    assert_eq!(defs.len(), 2);
+    assert_eq!(defs[1].name.as_slice(), "coin");
+    assert_eq!(frame_defs_filename("hero.png").as_slice(), "hero.frames");
+    assert_eq!(defs[1].pivot, Vector2::new(0.0, 16.0));
+
+    let mut frames: Vec<Frame> = range(0u, 2).map(|_| unsafe { zeroed() }).collect();
+    let region = Placement { x: 0, y: 0, width: 64, height: 64 };
+    cut_defined(frames.as_mut_slice(), &region, defs.as_slice(), 64.0, 64.0);
+    // Bottom left, y going up.
+    assert_eq!(frames[0].position, Vector2::new(0.0, 16.0));
+    assert_eq!(frames[0].pivot, Vector2::new(16.0, 0.0));
+    assert_eq!(frames[1].position, Vector2::new(32.0, 48.0));
+    assert_eq!(frames[1].size, Vector2::new(16.0, 16.0));
+
+    assert!(parse_frame_defs("coin 0 0 16").is_err());
+    assert!(parse_frame_defs("coin 0 0 16 16\ncoin 16 0 16 16").is_err());
+}
@@ -0,0 +1,126 @@
+// Runs the game without a window: no GLFW and no GL, just update with a
+// fixed delta and input read from a script. Prints (or dumps to a file) the
+// final Game, so scripted runs can be checked from outside.
+//
+// The script has one line per change of input: a frame number followed by
+// the actions held down from that frame on.
+//     # walk into the left wall, then let go
+//     0   move_left
+//     300
+// Blank lines and lines starting with # are skipped.
+
+use std::io::File;
+use std::time::duration::Duration;
+
+use platform;
+use save_state;
+use {HostOptions, open_game_lib, check_fits_in_memory, reserve_memory, split_memory,
+     OPTIONS_MEMORY_SIZE};
+
+// NOTE the dump prints arrays element by element, so it's a lot bigger than
+// Game itself.
+static DUMP_BUFFER_SIZE: uint = 256 * 1024;
+
+struct ScriptLine {
+    frame: uint,
+    // Space separated action names.
+    held:  String
+}
+
+fn read_script(path: &Path) -> Result<Vec<ScriptLine>, String> {
+    let text = match File::open(path).and_then(|mut file| file.read_to_string()) {
+        Ok(text) => text,
+        Err(e)   => return Err(format!("couldn't read {}: {}", path.display(), e))
+    };
+
+    let mut script: Vec<ScriptLine> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("#") { continue; }
+
+        let mut words = line.words();
+        let frame = match words.next().and_then(|w| from_str::<uint>(w)) {
+            Some(frame) => frame,
+            None => return Err(format!("{}:{}: expected a frame number",
+                                       path.display(), number + 1))
+        };
+        if script.last().map_or(false, |previous| previous.frame > frame) {
+            return Err(format!("{}:{}: frame {} comes before the line above it",
+                               path.display(), number + 1, frame));
+        }
+
+        script.push(ScriptLine {
+            frame: frame,
+            held:  words.collect::<Vec<&str>>().connect(" ")
+        });
+    }
+    Ok(script)
+}
+
+pub fn run(options: &HostOptions, script_path: &Path) {
+    let script = match read_script(script_path) {
+        Ok(script) => script,
+        Err(e)     => panic!("Can't run headless: {}", e)
+    };
+
+    let game_lib = match open_game_lib(&options.dylib_dir)
+                             .and_then(|lib| check_fits_in_memory(&lib.layout).map(|_| lib)) {
+        Ok(lib) => lib,
+        Err(e)  => panic!("Can't run the game library: {}", e)
+    };
+
+    let memory_block = reserve_memory();
+    let (game_memory, mut memory) = split_memory(&memory_block);
+    let mut options_memory = [0u8, ..OPTIONS_MEMORY_SIZE];
+
+    (game_lib.load_headless)(&platform::SERVICES, &mut game_memory[0], &mut memory,
+                             &mut options_memory[0]);
+
+    match options.start_state {
+        Some(ref path) => match save_state::load(path, &game_lib.layout, game_memory,
+                                                 options_memory.as_mut_slice(), &mut memory) {
+            Ok(()) => println!("Starting from {}", path.display()),
+            Err(e) => panic!("Can't start from {}: {}", path.display(), e)
+        },
+        None => {}
+    }
+
+    // NOTE always the same delta, so a script plays out the same every time.
+    let delta = Duration::nanoseconds((1.0e9 / options.target_fps).floor() as i64);
+
+    let mut held = "";
+    let mut next_line = 0u;
+    for frame in range(0, options.headless_frames) {
+        while next_line < script.len() && script[next_line].frame <= frame {
+            held = script[next_line].held.as_slice();
+            next_line += 1;
+        }
+
+        // Pretend the input got read at the start of each frame.
+        let time = frame as f64 * delta.num_nanoseconds().unwrap() as f64 / 1.0e9;
+        if !(game_lib.set_actions)(&mut options_memory[0], held.as_ptr(), held.len() as u64,
+                                   time) {
+            panic!("Frame {} of {} holds down actions the game doesn't have: {}",
+                   frame, script_path.display(), held);
+        }
+        (game_lib.update)(&mut game_memory[0], &mut memory, &options_memory[0], &delta);
+    }
+
+    let mut dump = Vec::from_elem(DUMP_BUFFER_SIZE, 0u8);
+    let dump_len = (game_lib.dump_game)(&game_memory[0], &mut dump[0], dump.len() as u64) as uint;
+    if dump_len == 0 {
+        panic!("Game dump doesn't fit in {} bytes.", DUMP_BUFFER_SIZE);
+    }
+    let dump = dump.slice_to(dump_len);
+
+    match options.dump_path {
+        Some(ref path) => match File::create(path).and_then(|mut file| file.write(dump)) {
+            Ok(()) => println!("Ran {} frames. Dumped Game to {}", options.headless_frames, path.display()),
+            Err(e) => panic!("Couldn't write {}: {}", path.display(), e)
+        },
+        None => {
+            println!("Ran {} frames. Game:", options.headless_frames);
+            print!("{}", String::from_utf8_lossy(dump));
+        }
+    }
+}
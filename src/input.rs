@@ -0,0 +1,159 @@
+// Polls GLFW and boils what happened down to a GameInput for the game, so
+// the game never has to know about GLFW.
+
+use glfw;
+use glfw::Action;
+use std::mem::zeroed;
+
+use GlfwEvent;
+
+// NOTE make sure this matches input::Device in the game.
+#[repr(C)]
+#[deriving(Copy, PartialEq, Show)]
+pub enum Device {
+    Keyboard,
+    Mouse,
+    Gamepad,
+    // Only the game uses these.
+    GamepadAxis
+}
+
+// NOTE make sure this matches input::MAX_BUTTON_TRANSITIONS in the game.
+pub const MAX_BUTTON_TRANSITIONS: uint = 32;
+
+// NOTE make sure this matches input::ButtonTransition in the game.
+#[repr(C)]
+#[deriving(Copy)]
+pub struct ButtonTransition {
+    pub device:  Device,
+    pub button:  i32,
+    pub pressed: bool,
+    pub time:    f64
+}
+
+// NOTE make sure these match the game's input.rs.
+pub const MAX_GAMEPAD_AXES:    uint = 8;
+pub const MAX_GAMEPAD_BUTTONS: uint = 32;
+
+// NOTE make sure this matches input::GamepadState in the game.
+#[repr(C)]
+#[deriving(Copy)]
+pub struct GamepadState {
+    pub connected:    bool,
+    pub axes:         [f32, ..MAX_GAMEPAD_AXES],
+    pub axis_count:   u32,
+    pub buttons:      [bool, ..MAX_GAMEPAD_BUTTONS],
+    pub button_count: u32
+}
+
+// NOTE make sure this matches input::GameInput in the game.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct GameInput {
+    pub button_transitions:      [ButtonTransition, ..MAX_BUTTON_TRANSITIONS],
+    pub button_transition_count: u32,
+
+    pub window_width:  i32,
+    pub window_height: i32,
+    pub resized:       bool,
+
+    pub close_requested: bool,
+
+    pub cursor_x: f32,
+    pub cursor_y: f32,
+    pub scroll_x: f32,
+    pub scroll_y: f32,
+
+    pub time: f64,
+
+    pub gamepad: GamepadState
+}
+
+impl GameInput {
+    fn push_button(&mut self, device: Device, button: i32, pressed: bool, time: f64) {
+        let count = self.button_transition_count as uint;
+        if count == MAX_BUTTON_TRANSITIONS {
+            println!("WARNING: More than {} button presses in one frame. Dropping {} {}.",
+                     MAX_BUTTON_TRANSITIONS, device, button);
+            return;
+        }
+        self.button_transitions[count] = ButtonTransition {
+            device: device, button: button, pressed: pressed, time: time
+        };
+        self.button_transition_count += 1;
+    }
+}
+
+// NOTE the window needs key, mouse button, scroll and close polling on.
+// Closing is left up to the game, so this puts should_close back after the
+// close button sets it.
+pub fn poll(glfw: &glfw::Glfw, window: &glfw::Window, events: &GlfwEvent) -> GameInput {
+    glfw.poll_events();
+
+    let mut input: GameInput = unsafe { zeroed() };
+    // NOTE the same clock GLFW stamps events with.
+    input.time = glfw.get_time();
+    let (width, height) = window.get_size();
+    input.window_width  = width;
+    input.window_height = height;
+    let (cursor_x, cursor_y) = window.get_cursor_pos();
+    input.cursor_x = cursor_x as f32;
+    input.cursor_y = cursor_y as f32;
+
+    for (time, event) in glfw::flush_messages(events) {
+        match event {
+            glfw::WindowEvent::Key(key, _, Action::Press, _) =>
+                input.push_button(Device::Keyboard, key as i32, true, time),
+            glfw::WindowEvent::Key(key, _, Action::Release, _) =>
+                input.push_button(Device::Keyboard, key as i32, false, time),
+
+            glfw::WindowEvent::MouseButton(button, Action::Press, _) =>
+                input.push_button(Device::Mouse, button as i32, true, time),
+            glfw::WindowEvent::MouseButton(button, Action::Release, _) =>
+                input.push_button(Device::Mouse, button as i32, false, time),
+
+            glfw::WindowEvent::Scroll(x, y) => {
+                input.scroll_x += x as f32;
+                input.scroll_y += y as f32;
+            }
+
+            glfw::WindowEvent::Size(_, _) => input.resized = true,
+
+            glfw::WindowEvent::Close => {
+                input.close_requested = true;
+                window.set_should_close(false);
+            }
+
+            _ => {}
+        }
+    }
+
+    input.gamepad = poll_gamepad(glfw);
+    input
+}
+
+// The first joystick that's plugged in, straight from GLFW. Deadzones and
+// the like are up to the game.
+fn poll_gamepad(glfw: &glfw::Glfw) -> GamepadState {
+    let mut state: GamepadState = unsafe { zeroed() };
+
+    let ids = vec![glfw::JoystickId::Joystick1, glfw::JoystickId::Joystick2,
+                   glfw::JoystickId::Joystick3, glfw::JoystickId::Joystick4];
+    let joystick = match ids.into_iter().map(|id| glfw.get_joystick(id)).find(|j| j.is_present()) {
+        Some(joystick) => joystick,
+        None           => return state
+    };
+    state.connected = true;
+
+    // NOTE anything past what fits just gets left off.
+    for (slot, axis) in state.axes.iter_mut().zip(joystick.get_axes().into_iter()) {
+        *slot = axis;
+        state.axis_count += 1;
+    }
+    for (slot, button) in state.buttons.iter_mut().zip(joystick.get_buttons().into_iter()) {
+        *slot = button != glfw::ffi::RELEASE;
+        state.button_count += 1;
+    }
+
+    state
+}
@@ -7,56 +7,177 @@ extern crate glfw;
 extern crate gl;
 extern crate cgmath;
 
-// extern crate dutchman_game;
+#[cfg(feature = "static")]
+extern crate dutchman_game;
 
 use glfw::{Context, Action, Key};
 
-use std::mem::{uninitialized, transmute, size_of, size_of_val};
+use std::mem::{uninitialized, transmute, transmute_copy, size_of, size_of_val};
 use gl::types::*;
-use libc::{c_void, c_char};
+use libc::{c_void, c_char, c_int};
 use std::ptr;
 use cgmath::*;
 use std::dynamic_lib::DynamicLibrary;
 use std::os;
-use std::io::fs;
+use std::os::{MemoryMap, MapOption};
+use pacing::{FramePacing, FramePacer};
+use input::GameInput;
+use platform::PlatformServices;
+use std::io::{fs, IoError};
 use std::io::fs::PathExtensions;
 use std::mem;
+use std::any::{Any, AnyRefExt};
+use std::rt::unwind;
 use std::str;
+use std::slice;
 use std::io::timer::sleep;
 use std::time::duration::Duration;
 use std::num::Float;
 
+mod save_state;
+mod pacing;
+mod headless;
+mod input;
+mod platform;
+
 type GlfwEvent = Receiver<(f64, glfw::WindowEvent)>;
-type TestLoopFn = extern "C" fn(&mut u8, &mut u8, &mut u8, &Duration, &glfw::Glfw, &glfw::Window, &GlfwEvent);
-type LoadFn = extern "C" fn(bool, &u8, &glfw::Window, &mut u8, &mut u8, &mut u8);
+type ProcessInputFn = extern "C" fn(&mut u8, &mut u8, &GameInput) -> bool;
+type UpdateFn = extern "C" fn(&mut u8, &mut GameMemory, &u8, &Duration);
+type RenderFn = extern "C" fn(&u8, &mut u8, &mut GameMemory);
+type LoadFn = extern "C" fn(bool, &PlatformServices, i32, i32, &mut u8, &mut GameMemory, &mut u8, &mut u8);
+type LayoutDescriptorFn = extern "C" fn() -> LayoutDescriptor;
+type SaveGameFn = extern "C" fn(&u8, &mut u8, u64) -> u64;
+type RestoreGameFn = extern "C" fn(&PlatformServices, &mut u8, &u8, u64);
+type LoadHeadlessFn = extern "C" fn(&PlatformServices, &mut u8, &mut GameMemory, &mut u8);
+type SetActionsFn = extern "C" fn(&mut u8, *const u8, u64, f64) -> bool;
+type DumpGameFn = extern "C" fn(&u8, &mut u8, u64) -> u64;
+type DylibUpdate = GameLib;
 
-static DYLIB_DIR: &'static str = "./dutchman-game";
+static WINDOW_TITLE: &'static str = "The Flying Dutchman's Prisoner";
 
-extern "C" {
-    // === GLFW stuff: ===
-    pub fn glfwGetCurrentContext() -> u64;
-    pub fn glfwSetTestIdent(i: int);
-    pub fn glfwTestIdent() -> int;
-    static _glfw: u8;
+// Shift+F1..F4 saves to a slot, F1..F4 loads it back.
+static SAVE_STATE_KEYS: [Key, ..4] = [Key::F1, Key::F2, Key::F3, Key::F4];
+
+// NOTE make sure this matches abi::ABI_VERSION in the game.
+static ABI_VERSION: u32 = 20;
+
+// NOTE GAME_MEMORY_SIZE only has to fit the Game struct itself. Anything
+// variable-sized goes in the arenas.
+// NOTE make sure the first three match the ones in the game's abi.rs.
+const GAME_MEMORY_SIZE:      uint = 64 * 1024;
+const OPTIONS_MEMORY_SIZE:   uint = 4096;
+const GL_MEMORY_SIZE:        uint = 1024;
+const PERMANENT_MEMORY_SIZE: uint = 64 * 1024 * 1024;
+const TRANSIENT_MEMORY_SIZE: uint = 16 * 1024 * 1024;
+const ASSET_MEMORY_SIZE:     uint = 16 * 1024 * 1024;
+
+// NOTE game memory is always reserved at the same address so that pointers
+// in it are still good when a save state gets loaded in another session.
+#[cfg(target_word_size = "64")]
+const MEMORY_BASE: uint = 0x200_0000_0000; // 2 TB
+#[cfg(target_word_size = "32")]
+const MEMORY_BASE: uint = 0x4000_0000; // 1 GB
+// NOTE the saved fields carry their names and types, so this needs some
+// room on top of GAME_MEMORY_SIZE.
+const MIGRATION_BUFFER_SIZE: uint = GAME_MEMORY_SIZE * 2;
+
+// NOTE make sure this matches abi::LayoutDescriptor in the game.
+#[repr(C)]
+#[deriving(Copy)]
+#[allow(missing_copy_implementations)]
+pub struct LayoutDescriptor {
+    pub abi_version: u32,
+
+    pub game_size: u64,
+    pub game_hash: u64,
+
+    pub options_size: u64,
+    pub options_hash: u64,
+    // How many bytes at the start of Options are input. Input loops,
+    // rollbacks and save states only put those back, leaving config and
+    // what the devices were doing as they are.
+    pub options_input_size: u64,
+
+    pub gl_data_size: u64,
+    pub gl_data_hash: u64
 }
 
-fn game_dylib_path() -> Option<Path> {
-    // NOTE this assumes we are running from the project root.
-    // let game_path = Path::new("./dutchman-game/dutchman_game.dll");
-    // os::make_absolute(&game_path).unwrap()
+// NOTE make sure this matches memory::Arena in the game.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct Arena {
+    pub base: *mut u8,
+    pub size: uint,
+    pub used: uint
+}
 
-    let dir = Path::new(DYLIB_DIR);
-    let contents = fs::readdir(&dir).unwrap();
+impl Arena {
+    fn new(storage: &mut [u8]) -> Arena {
+        Arena { base: storage.as_mut_ptr(), size: storage.len(), used: 0 }
+    }
+
+    // Everything the game has pushed so far.
+    fn used_bytes(&self) -> &[u8] {
+        unsafe { slice::from_raw_buf(&(self.base as *const u8), self.used) }
+    }
+
+    // Puts back what used_bytes returned at some earlier point.
+    fn restore(&mut self, bytes: &[u8]) {
+        assert!(bytes.len() <= self.size);
+        unsafe {
+            let storage = slice::from_raw_mut_buf(&self.base, bytes.len());
+            slice::bytes::copy_memory(storage, bytes);
+        }
+        self.used = bytes.len();
+    }
+}
+
+// NOTE make sure this matches memory::GameMemory in the game.
+#[repr(C)]
+pub struct GameMemory {
+    pub permanent: Arena,
+    pub transient: Arena,
+    // NOTE input loops, save states and rollbacks never touch this one. It
+    // holds what the game read out of the asset files, which has to keep
+    // matching the textures it loaded.
+    pub assets: Arena
+}
+
+// NOTE on Linux cargo names the library libdutchman_game-<hash>.so, which
+// still contains "dutchman_game".
+fn is_game_dylib(file_name: &str) -> bool {
+    file_name.contains("dutchman_game") && file_name.ends_with(os::consts::DLL_SUFFIX)
+}
+
+// The newest build in dir. Older ones stick around when a build gets
+// refused or the host wasn't running to clean them up.
+fn game_dylib_path(dir: &Path) -> Option<Path> {
+    let contents = match fs::readdir(dir) {
+        Ok(contents) => contents,
+        Err(e) => panic!("Couldn't look for the game dylib in {}: {}", dir.display(), e)
+    };
+    let mut newest: Option<(u64, &Path)> = None;
     for entry in contents.iter() {
         if entry.is_dir() { continue; }
         let file_name = entry.filename_str().unwrap();
+        if !is_game_dylib(file_name) { continue; }
 
-        if file_name.contains("dutchman_game") && file_name.contains(".dll") {
-            return Some(os::make_absolute(entry).unwrap());
+        let modified = match entry.stat() {
+            Ok(stat) => stat.modified,
+            Err(_)   => continue
+        };
+        match newest {
+            Some((newest_modified, _)) if newest_modified >= modified => {}
+            _ => newest = Some((modified, entry))
+        }
+    }
+    match newest {
+        Some((_, path)) => Some(os::make_absolute(path).unwrap()),
+        None => {
+            println!("WARNING: Failed to find game dylib path!");
+            None
         }
     }
-    println!("WARNING: Failed to find game dylib path!");
-    return None;
 }
 
 fn load_game_dylib_from(path: &Path) -> DynamicLibrary {
@@ -66,32 +187,288 @@ fn load_game_dylib_from(path: &Path) -> DynamicLibrary {
     }
 }
 
-fn load_game_dylib() -> DynamicLibrary {
-    match DynamicLibrary::open(game_dylib_path()) {
+fn load_game_dylib(dir: &Path) -> DynamicLibrary {
+    match DynamicLibrary::open(game_dylib_path(dir)) {
         Ok(l) => l,
         Err(e) => panic!("Couldn't load game lib: {}", e)
     }
 }
 
-fn test_loop_fn(lib: &DynamicLibrary) -> (LoadFn, TestLoopFn) {
-    unsafe {
-        let test_loop: TestLoopFn = match lib.symbol::<u8>("update_and_render") {
-            Ok(f) => transmute(f),
-            Err(e) => panic!("Damn! {}", e)
-        };
+// A loaded game library along with everything we pull out of it.
+struct GameLib {
+    // NOTE these are None when the game is linked in statically.
+    path:         Option<Path>,
+    lib:          Option<DynamicLibrary>,
+    load:          LoadFn,
+    process_input: ProcessInputFn,
+    update:        UpdateFn,
+    render:        RenderFn,
+    save_game:    SaveGameFn,
+    restore_game: RestoreGameFn,
+    // Only used when running headless.
+    load_headless: LoadHeadlessFn,
+    set_actions:   SetActionsFn,
+    dump_game:     DumpGameFn,
+    layout:       LayoutDescriptor
+}
 
-        let load: LoadFn = match lib.symbol::<u8>("load") {
-            Ok(f) => transmute(f),
-            Err(e) => panic!(";_;")
-        };
+unsafe fn game_lib_symbol<T>(lib: &DynamicLibrary, name: &str) -> Result<T, String> {
+    match lib.symbol::<u8>(name) {
+        Ok(f)  => Ok(transmute_copy(&f)),
+        Err(_) => Err(format!("library does not export {}", name))
+    }
+}
+
+impl GameLib {
+    fn open(path: &Path) -> Result<GameLib, String> {
+        let lib = load_game_dylib_from(path);
+
+        unsafe {
+            let describe: LayoutDescriptorFn = try!(game_lib_symbol(&lib, "layout_descriptor"));
+
+            Ok(GameLib {
+                path:         Some(path.clone()),
+                load:          try!(game_lib_symbol(&lib, "load")),
+                process_input: try!(game_lib_symbol(&lib, "process_input")),
+                update:       try!(game_lib_symbol(&lib, "update")),
+                render:       try!(game_lib_symbol(&lib, "render")),
+                save_game:    try!(game_lib_symbol(&lib, "save_game")),
+                restore_game: try!(game_lib_symbol(&lib, "restore_game")),
+                load_headless: try!(game_lib_symbol(&lib, "load_headless")),
+                set_actions:   try!(game_lib_symbol(&lib, "set_actions")),
+                dump_game:     try!(game_lib_symbol(&lib, "dump_game")),
+                layout:       describe(),
+                lib:          Some(lib)
+            })
+        }
+    }
+
+    #[cfg(feature = "static")]
+    fn linked() -> GameLib {
+        GameLib {
+            path:         None,
+            lib:          None,
+            load:          linked::load,
+            process_input: linked::process_input,
+            update:       linked::update,
+            render:       linked::render,
+            save_game:    linked::save_game,
+            restore_game: linked::restore_game,
+            load_headless: linked::load_headless,
+            set_actions:   linked::set_actions,
+            dump_game:     linked::dump_game,
+            layout:       unsafe { transmute(dutchman_game::layout_descriptor()) }
+        }
+    }
+}
 
-        (load, test_loop)
+#[cfg(not(feature = "static"))]
+fn open_game_lib(dylib_dir: &Path) -> Result<GameLib, String> {
+    match game_dylib_path(dylib_dir) {
+        Some(path) => GameLib::open(&path),
+        None => Err(format!("there's no game dylib in {}", dylib_dir.display()))
     }
 }
 
-fn static_test_loop_fn(lib: &DynamicLibrary) -> () {
-    panic!("Hey make this make sense.");
-    // dutchman_game::old_test_loop
+#[cfg(feature = "static")]
+fn open_game_lib(dylib_dir: &Path) -> Result<GameLib, String> {
+    Ok(GameLib::linked())
+}
+
+// Plain calls into the statically linked game, with the same memory block
+// signatures as the symbols we'd pull out of the dylib.
+#[cfg(feature = "static")]
+mod linked {
+    use dutchman_game;
+    use std::mem::transmute;
+    use std::time::duration::Duration;
+    use GameMemory;
+    use input::GameInput;
+    use platform::PlatformServices;
+
+    pub extern "C" fn load(fresh_load: bool, services: &PlatformServices,
+                           window_width: i32, window_height: i32,
+                           game: &mut u8, memory: &mut GameMemory,
+                           options: &mut u8, gl_data: &mut u8) {
+        unsafe {
+            dutchman_game::load(fresh_load, transmute(services), window_width, window_height,
+                                transmute(game), transmute(memory),
+                                transmute(options), transmute(gl_data))
+        }
+    }
+
+    pub extern "C" fn process_input(options: &mut u8, gl_data: &mut u8, input: &GameInput) -> bool {
+        unsafe {
+            dutchman_game::process_input(transmute(options), transmute(gl_data), transmute(input))
+        }
+    }
+
+    pub extern "C" fn update(game: &mut u8, memory: &mut GameMemory,
+                             options: &u8, delta: &Duration) {
+        unsafe {
+            dutchman_game::update(transmute(game), transmute(memory), transmute(options), delta)
+        }
+    }
+
+    pub extern "C" fn render(game: &u8, gl_data: &mut u8, memory: &mut GameMemory) {
+        unsafe { dutchman_game::render(transmute(game), transmute(gl_data), transmute(memory)) }
+    }
+
+    pub extern "C" fn save_game(game: &u8, buffer: &mut u8, buffer_len: u64) -> u64 {
+        unsafe { dutchman_game::save_game(transmute(game), buffer, buffer_len) }
+    }
+
+    pub extern "C" fn restore_game(services: &PlatformServices,
+                                   game: &mut u8, data: &u8, data_len: u64) {
+        unsafe { dutchman_game::restore_game(transmute(services), transmute(game), data, data_len) }
+    }
+
+    pub extern "C" fn load_headless(services: &PlatformServices,
+                                    game: &mut u8, memory: &mut GameMemory, options: &mut u8) {
+        unsafe {
+            dutchman_game::load_headless(transmute(services), transmute(game), transmute(memory),
+                                         transmute(options))
+        }
+    }
+
+    pub extern "C" fn set_actions(options: &mut u8, names: *const u8, names_len: u64,
+                                  time: f64) -> bool {
+        unsafe { dutchman_game::set_actions(transmute(options), names, names_len, time) }
+    }
+
+    pub extern "C" fn dump_game(game: &u8, buffer: &mut u8, buffer_len: u64) -> u64 {
+        unsafe { dutchman_game::dump_game(transmute(game), buffer, buffer_len) }
+    }
+}
+
+// NOTE all of this is reserved once up front and never moves, so the game
+// can keep pointers into it across reloads.
+fn reserve_memory() -> MemoryMap {
+    let memory_block = MemoryMap::new(
+        GAME_MEMORY_SIZE + PERMANENT_MEMORY_SIZE + TRANSIENT_MEMORY_SIZE + ASSET_MEMORY_SIZE,
+        &[MapOption::MapReadable, MapOption::MapWritable,
+          MapOption::MapAddr(MEMORY_BASE as *const u8)]
+    ).unwrap();
+    if memory_block.data() as uint != MEMORY_BASE {
+        println!("WARNING: Couldn't reserve memory at {:x}. Save states won't load in other sessions.",
+                 MEMORY_BASE);
+    }
+    memory_block
+}
+
+// Carves the reserved block up into the Game block and the arenas.
+fn split_memory<'a>(memory_block: &'a MemoryMap) -> (&'a mut [u8], GameMemory) {
+    let memory_base = memory_block.data();
+    let storage: &'a mut [u8] = unsafe {
+        transmute(slice::from_raw_mut_buf(&memory_base, memory_block.len()))
+    };
+    let (game_memory, arena_storage) = storage.split_at_mut(GAME_MEMORY_SIZE);
+    let (permanent_storage, rest) = arena_storage.split_at_mut(PERMANENT_MEMORY_SIZE);
+    let (transient_storage, asset_storage) = rest.split_at_mut(TRANSIENT_MEMORY_SIZE);
+
+    let memory = GameMemory {
+        permanent: Arena::new(permanent_storage),
+        transient: Arena::new(transient_storage),
+        assets:    Arena::new(asset_storage.slice_to_mut(ASSET_MEMORY_SIZE))
+    };
+    (game_memory, memory)
+}
+
+// Makes sure this host can run the library at all: same ABI version, and
+// memory blocks in main that are big enough for its structs.
+fn check_fits_in_memory(layout: &LayoutDescriptor) -> Result<(), String> {
+    if layout.abi_version != ABI_VERSION {
+        return Err(format!("ABI version is {} but the host expects {}",
+                           layout.abi_version, ABI_VERSION));
+    }
+
+    let blocks = [
+        ("Game",    layout.game_size,    GAME_MEMORY_SIZE),
+        ("Options", layout.options_size, OPTIONS_MEMORY_SIZE),
+        ("GlData",  layout.gl_data_size, GL_MEMORY_SIZE)
+    ];
+    for &(name, size, available) in blocks.iter() {
+        if size > available as u64 {
+            return Err(format!("{} is {} bytes but only {} are reserved for it",
+                               name, size, available));
+        }
+    }
+    Ok(())
+}
+
+// What needs to happen to the memory blocks when swapping in a new library.
+enum Reload {
+    // Everything is laid out the same; just swap the functions.
+    InPlace,
+    // Game changed shape, so it has to go through save_game/restore_game.
+    MigrateGame
+}
+
+// A new library is only allowed to take over Options and GlData if it
+// agrees on their layout. Game can be migrated field by field.
+fn check_compatible(current: &LayoutDescriptor, new: &LayoutDescriptor) -> Result<Reload, String> {
+    try!(check_fits_in_memory(new));
+
+    let blocks = [
+        ("Options", current.options_size, new.options_size, current.options_hash, new.options_hash),
+        ("GlData",  current.gl_data_size, new.gl_data_size, current.gl_data_hash, new.gl_data_hash)
+    ];
+    for &(name, current_size, new_size, current_hash, new_hash) in blocks.iter() {
+        if current_size != new_size {
+            return Err(format!("{} changed size from {} to {} bytes", name, current_size, new_size));
+        }
+        if current_hash != new_hash {
+            return Err(format!("{} changed layout (hash {:x} -> {:x})", name, current_hash, new_hash));
+        }
+    }
+
+    if current.game_size != new.game_size || current.game_hash != new.game_hash {
+        Ok(Reload::MigrateGame)
+    } else {
+        Ok(Reload::InPlace)
+    }
+}
+
+// Loads the library at the given path and sends it to the main loop.
+// NOTE main checks it against whatever's actually running, since only it
+// knows about rollbacks and migrations that didn't happen.
+fn send_new_dylib(file: &Path, tx: &Sender<DylibUpdate>) {
+    match GameLib::open(file) {
+        Ok(lib) => tx.send(lib),
+        Err(e)  => {
+            println!("REFUSING TO LOAD {}: {}", file.display(), e);
+            println!("Restart the game to pick up this build.");
+        }
+    }
+}
+
+// Has the old library write out its Game field by field, then has the new
+// one read back whatever fields it still has.
+fn migrate_game(old: &GameLib, new: &GameLib, game_memory: &mut [u8]) -> bool {
+    let mut saved = Vec::from_elem(MIGRATION_BUFFER_SIZE, 0u8);
+    let saved_len = (old.save_game)(&game_memory[0], &mut saved[0], saved.len() as u64);
+    if saved_len == 0 {
+        println!("ERROR: Game doesn't fit in {} bytes of migration buffer. Keeping the old build.",
+                 MIGRATION_BUFFER_SIZE);
+        return false;
+    }
+
+    (new.restore_game)(&platform::SERVICES, &mut game_memory[0], &saved[0], saved_len);
+    true
+}
+
+#[cfg(not(feature = "static"))]
+fn watch_for_reloads(dylib_dir: Path) -> Receiver<DylibUpdate> {
+    let (tx, rx) = channel();
+    spawn(move || watch_for_updated_dll(&tx, &dylib_dir));
+    rx
+}
+
+// NOTE nothing to reload when the game is linked in, so this never receives.
+#[cfg(feature = "static")]
+fn watch_for_reloads(dylib_dir: Path) -> Receiver<DylibUpdate> {
+    let (_, rx) = channel();
+    rx
 }
 
 // === Winapi stuff for file listening. ===
@@ -112,9 +489,9 @@ static FILE_NOTIFY_CHANGE_LAST_WRITE: int = 0x00000010;
 static INVALID_HANDLE_VALUE: *const c_void = -1 as *const c_void;
 
 #[cfg(target_os = "windows")]
-fn watch_for_updated_dll(tx: &Sender<(Path, DynamicLibrary, LoadFn, TestLoopFn)>) {
+fn watch_for_updated_dll(tx: &Sender<DylibUpdate>, dylib_dir: &Path) {
     // current dylib filename
-    let mut current_dylib_path = game_dylib_path().unwrap();
+    let mut current_dylib_path = game_dylib_path(dylib_dir).unwrap();
     let dylib_dir = current_dylib_path.dir_path();
 
     unsafe {
@@ -135,20 +512,14 @@ fn watch_for_updated_dll(tx: &Sender<(Path, DynamicLibrary, LoadFn, TestLoopFn)>
                         if file.is_dir() { continue; }
                         let file_name = file.filename_str().unwrap();
 
-                        if file_name.contains("dutchman_game") &&
-                           file_name.contains(".dll") &&
+                        if is_game_dylib(file_name) &&
                            file_name != current_dylib_path.filename_str().unwrap()
                         {
                             println!("New game dylib path: {}\nCurrent game dylib path: {}",
                                      file.display(), current_dylib_path.display());
                             println!("-----------");
 
-                            let lib = load_game_dylib_from(file);
-                            match test_loop_fn(&lib) {
-                                (load_fn, test_loop_fn) => {
-                                    tx.send((file.clone(), lib, load_fn, test_loop_fn));
-                                }
-                            }
+                            send_new_dylib(file, tx);
 
                             current_dylib_path = file.clone();
                             break;
@@ -170,111 +541,692 @@ fn watch_for_updated_dll(tx: &Sender<(Path, DynamicLibrary, LoadFn, TestLoopFn)>
     }
 }
 
+// === Inotify stuff for file listening. ===
+#[cfg(target_os = "linux")]
+extern "C" {
+    pub fn inotify_init() -> c_int;
+    pub fn inotify_add_watch(fd: c_int, path: *const c_char, mask: u32) -> c_int;
+}
+#[cfg(target_os = "linux")]
+static IN_CLOSE_WRITE: u32 = 0x00000008;
+#[cfg(target_os = "linux")]
+static IN_MOVED_TO: u32 = 0x00000080;
+// NOTE struct inotify_event { int wd; uint32_t mask; uint32_t cookie; uint32_t len; char name[]; }
+#[cfg(target_os = "linux")]
+static INOTIFY_EVENT_SIZE: uint = 16;
+
+#[cfg(target_os = "linux")]
+fn watch_for_updated_dll(tx: &Sender<DylibUpdate>, dylib_dir: &Path) {
+    // current dylib filename
+    let mut current_dylib_path = game_dylib_path(dylib_dir).unwrap();
+    let dylib_dir = current_dylib_path.dir_path();
+
+    unsafe {
+        let fd = inotify_init();
+        if fd < 0 {
+            panic!("Failed to initialize inotify: {}", os::last_os_error());
+        }
+
+        // NOTE IN_CLOSE_WRITE rather than IN_MODIFY, so we never dlopen a
+        // half-copied library.
+        let watch = dylib_dir.with_c_str(|s|
+            inotify_add_watch(fd, s, IN_CLOSE_WRITE | IN_MOVED_TO)
+        );
+        if watch < 0 {
+            panic!("Failed to watch {}: {}", dylib_dir.display(), os::last_os_error());
+        }
+
+        let mut buf = [0u8, ..4096];
+        loop {
+            let len = libc::read(fd, transmute(&mut buf[0]), buf.len() as libc::size_t);
+            if len < 0 {
+                panic!("Error occurred during directory wait! {}", os::last_os_error());
+            }
+
+            let mut offset = 0u;
+            while offset + INOTIFY_EVENT_SIZE <= len as uint {
+                let name_len = *transmute::<&u8, *const u32>(&buf[offset + 12]) as uint;
+                let name_start = offset + INOTIFY_EVENT_SIZE;
+                offset = name_start + name_len;
+
+                // The name is null-padded out to name_len.
+                let name_bytes = buf.slice(name_start, offset);
+                let name_end = match name_bytes.iter().position(|b| *b == 0) {
+                    Some(i) => i,
+                    None    => name_len
+                };
+                let file_name = match str::from_utf8(name_bytes.slice_to(name_end)) {
+                    Some(s) => s,
+                    None    => continue
+                };
+
+                if !is_game_dylib(file_name) ||
+                   file_name == current_dylib_path.filename_str().unwrap()
+                {
+                    continue;
+                }
+
+                let file = dylib_dir.join(file_name);
+                println!("New game dylib path: {}\nCurrent game dylib path: {}",
+                         file.display(), current_dylib_path.display());
+                println!("-----------");
+
+                send_new_dylib(&file, tx);
+
+                current_dylib_path = file;
+            }
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn watch_for_updated_dll(tx: &Sender<DylibUpdate>, dylib_dir: &Path) {
+}
+
+// Handmade-style looped live editing. Press L once to start recording, again
+// to play the recording back forever (while hot reloading), and once more
+// to go back to live input.
+enum LoopState {
+    Live,
+    Recording,
+    Playing(uint)
+}
+
+struct InputLoop {
+    state: LoopState,
+
+    // Game memory as it was when recording started.
+    game_snapshot:      Vec<u8>,
+    permanent_snapshot: Vec<u8>,
+
+    // The input part of Options (see options_input_size) as it was after
+    // process_input, along with the delta, for every recorded frame.
+    // NOTE everything here gets handed just that part of Options, so the
+    // bindings and device state stay live through playback.
+    frames: Vec<(Vec<u8>, Duration)>,
+
+    // Input as it was before playback started, to go back to after.
+    live_options: Vec<u8>,
+
+    key_was_down: bool
+}
+
+impl InputLoop {
+    fn new() -> InputLoop {
+        InputLoop {
+            state:              LoopState::Live,
+            game_snapshot:      Vec::new(),
+            permanent_snapshot: Vec::new(),
+            frames:             Vec::new(),
+            live_options:       Vec::new(),
+            key_was_down:       false
+        }
+    }
+
+    fn playing(&self) -> bool {
+        match self.state { LoopState::Playing(_) => true, _ => false }
+    }
+
+    fn recording(&self) -> bool {
+        match self.state { LoopState::Recording => true, _ => false }
+    }
+
+    // Moves on to the next state when the loop key goes down.
+    fn check_key(&mut self, key_down: bool,
+                 game_memory: &[u8], memory: &GameMemory, options_memory: &mut [u8]) {
+        let pressed = key_down && !self.key_was_down;
+        self.key_was_down = key_down;
+        if !pressed { return; }
+
+        match self.state {
+            LoopState::Live => {
+                println!("Recording input loop...");
+                self.game_snapshot      = game_memory.to_vec();
+                self.permanent_snapshot = memory.permanent.used_bytes().to_vec();
+                self.frames.clear();
+                self.state = LoopState::Recording;
+            }
+
+            LoopState::Recording => {
+                if self.frames.is_empty() {
+                    println!("Nothing recorded.");
+                    self.state = LoopState::Live;
+                } else {
+                    println!("Playing back {} frames.", self.frames.len());
+                    self.live_options = options_memory.to_vec();
+                    self.state = LoopState::Playing(0);
+                }
+            }
+
+            LoopState::Playing(_) => self.stop(options_memory)
+        }
+    }
+
+    fn stop(&mut self, options_memory: &mut [u8]) {
+        match self.state {
+            LoopState::Playing(_) => {
+                slice::bytes::copy_memory(options_memory, self.live_options.as_slice());
+                println!("Back to live input.");
+            }
+            LoopState::Recording => println!("Stopped recording."),
+            LoopState::Live      => {}
+        }
+        self.state = LoopState::Live;
+    }
+
+    fn record(&mut self, options_memory: &[u8], delta: &Duration) {
+        self.frames.push((options_memory.to_vec(), delta.clone()));
+    }
+
+    // Puts the next recorded frame's input in place (jumping back to the
+    // snapshot at the start of the loop) and returns its delta.
+    fn play_frame(&mut self, game_memory: &mut [u8], memory: &mut GameMemory,
+                  options_memory: &mut [u8]) -> Duration {
+        let index = match self.state {
+            LoopState::Playing(i) => i,
+            _ => panic!("play_frame called while not playing")
+        };
+
+        if index == 0 {
+            slice::bytes::copy_memory(game_memory, self.game_snapshot.as_slice());
+            memory.permanent.restore(self.permanent_snapshot.as_slice());
+        }
+
+        let delta = {
+            let (ref recorded_options, ref delta) = self.frames[index];
+            slice::bytes::copy_memory(options_memory, recorded_options.as_slice());
+            delta.clone()
+        };
+
+        self.state = LoopState::Playing((index + 1) % self.frames.len());
+        delta
+    }
+}
+
+// Copies of every memory block the game owns. Only the input part of
+// Options, like with input loops.
+struct MemorySnapshot {
+    game:      Vec<u8>,
+    permanent: Vec<u8>,
+    options:   Vec<u8>,
+    gl:        Vec<u8>
+}
+
+impl MemorySnapshot {
+    fn take(game_memory: &[u8], memory: &GameMemory,
+            options_memory: &[u8], gl_memory: &[u8]) -> MemorySnapshot {
+        MemorySnapshot {
+            game:      game_memory.to_vec(),
+            permanent: memory.permanent.used_bytes().to_vec(),
+            options:   options_memory.to_vec(),
+            gl:        gl_memory.to_vec()
+        }
+    }
+
+    fn restore(&self, game_memory: &mut [u8], memory: &mut GameMemory,
+               options_memory: &mut [u8], gl_memory: &mut [u8]) {
+        slice::bytes::copy_memory(game_memory, self.game.as_slice());
+        memory.permanent.restore(self.permanent.as_slice());
+        slice::bytes::copy_memory(options_memory, self.options.as_slice());
+        slice::bytes::copy_memory(gl_memory, self.gl.as_slice());
+    }
+}
+
+// NOTE once a new library has run this many frames without panicking, we
+// let go of the old one.
+static ROLLBACK_FRAMES: uint = 120;
+
+// The library that was running before the latest reload, along with memory
+// from right before the reload, in case the new one falls over right away.
+struct Rollback {
+    lib:         GameLib,
+    memory:      MemorySnapshot,
+    frames_left: uint
+}
+
+// How many times to try deleting a dylib Windows still has locked, and how
+// long to wait in between.
+static REMOVE_DYLIB_TRIES: uint = 10;
+static REMOVE_DYLIB_WAIT_MS: i64 = 20;
+
+// NOTE Windows can keep a dylib locked for a moment after it's unloaded. std
+// reports that sharing violation as an OtherIoError (or PermissionDenied
+// for a DLL that's still mapped).
+#[cfg(target_os = "windows")]
+fn still_locked(e: &IoError) -> bool {
+    use std::io::IoErrorKind;
+    e.kind == IoErrorKind::OtherIoError || e.kind == IoErrorKind::PermissionDenied
+}
 #[cfg(not(target_os = "windows"))]
-fn watch_for_updated_dll(tx: Sender<(LoadFn, TestLoopFn)>) {
+fn still_locked(_: &IoError) -> bool { false }
+
+// Unloads the library and deletes the file it was loaded from.
+fn remove_dylib(lib: GameLib) {
+    let path = lib.path.clone();
+    // NOTE Windows won't let us delete it while it's still loaded.
+    drop(lib);
+
+    let path = match path {
+        Some(path) => path,
+        None       => return
+    };
+    for attempt in range(1, REMOVE_DYLIB_TRIES + 1) {
+        match fs::unlink(&path) {
+            Ok(()) => return,
+            Err(ref e) if still_locked(e) && attempt < REMOVE_DYLIB_TRIES =>
+                sleep(Duration::milliseconds(REMOVE_DYLIB_WAIT_MS)),
+            Err(e) => {
+                println!("WARNING: Couldn't delete {}: {}", path.display(), e);
+                return;
+            }
+        }
+    }
 }
 
-#[link(name = "Winmm")]
-extern "C" {
-    // NOTE needed for accurate frame delay on Windows only.
-    pub fn timeBeginPeriod(period: uint) -> uint;
-    pub fn timeEndPeriod(period: uint);
+fn panic_message(cause: Box<Any + Send>) -> String {
+    match cause.downcast_ref::<&'static str>() {
+        Some(s) => s.to_string(),
+        None => match cause.downcast_ref::<String>() {
+            Some(s) => s.clone(),
+            None    => "(no message)".to_string()
+        }
+    }
+}
+
+// Runs f, and if guarded, catches it panicking instead of going down with it.
+fn guard<F: FnOnce()>(guarded: bool, f: F) -> Result<(), String> {
+    if !guarded {
+        f();
+        return Ok(());
+    }
+
+    match unsafe { unwind::try(f) } {
+        Ok(())     => Ok(()),
+        Err(cause) => Err(panic_message(cause))
+    }
+}
+
+// NOTE cargo puts the binary in target/, so by default everything is found
+// relative to the directory above that, wherever we were run from.
+fn project_root() -> Path {
+    match os::self_exe_path() {
+        Some(exe_dir) => exe_dir.join(".."),
+        None => {
+            println!("WARNING: Couldn't get executable path. Assuming we're in the project root.");
+            Path::new(".")
+        }
+    }
+}
+
+// "640x480" -> (640, 480)
+fn parse_size(s: &str) -> Option<(u32, u32)> {
+    let parts: Vec<&str> = s.split('x').collect();
+    if parts.len() != 2 { return None; }
+
+    match (from_str::<u32>(parts[0]), from_str::<u32>(parts[1])) {
+        (Some(width), Some(height)) if width > 0 && height > 0 => Some((width, height)),
+        _ => None
+    }
+}
+
+// Things about the host that can be changed from the command line.
+struct HostOptions {
+    window_width:  u32,
+    window_height: u32,
+    fullscreen:    bool,
+
+    frame_pacing: FramePacing,
+    target_fps:   f64,
+
+    dylib_dir:      Path,
+    asset_dir:      Path,
+    // Where the game keeps files the player can edit, like key bindings.
+    config_dir:     Path,
+    save_state_dir: Path,
+    // A save state to start from instead of a fresh game.
+    start_state:    Option<Path>,
+
+    // Set to run without a window, off the input script at this path.
+    headless:        Option<Path>,
+    headless_frames: uint,
+    // Where to write the final Game when headless. Printed if None.
+    dump_path:       Option<Path>
+}
+
+impl HostOptions {
+    fn default() -> HostOptions {
+        let root = project_root();
+
+        HostOptions {
+            window_width:  480,
+            window_height: 480,
+            fullscreen:    false,
+
+            frame_pacing: FramePacing::Sleep,
+            target_fps:   60.0,
+
+            dylib_dir:      root.join("dutchman-game"),
+            asset_dir:      root.join("assets"),
+            config_dir:     root.clone(),
+            save_state_dir: root.join("saves"),
+            start_state:    None,
+
+            headless:        None,
+            headless_frames: 600,
+            dump_path:       None
+        }
+    }
+
+    fn from_args(args: &[String]) -> HostOptions {
+        let mut options = HostOptions::default();
+
+        let mut args = args.iter().skip(1);
+        loop {
+            let arg = match args.next() { Some(a) => a, None => break };
+
+            // The argument after this one.
+            macro_rules! value(
+                () => (match args.next() {
+                    Some(v) => v.as_slice(),
+                    None    => panic!("{} needs a value", arg)
+                })
+            );
+
+            match arg.as_slice() {
+                "--size" => {
+                    let value = value!();
+                    match parse_size(value) {
+                        Some((width, height)) => {
+                            options.window_width  = width;
+                            options.window_height = height;
+                        }
+                        None => panic!("--size should look like 640x480 (got \"{}\")", value)
+                    }
+                }
+
+                "--fullscreen" => options.fullscreen = true,
+
+                "--pacing" => {
+                    let value = value!();
+                    options.frame_pacing = match FramePacing::from_str(value) {
+                        Some(pacing) => pacing,
+                        None => panic!("--pacing should be vsync, sleep or uncapped (got \"{}\")", value)
+                    };
+                }
+
+                "--fps" => {
+                    let value = value!();
+                    options.target_fps = match from_str::<f64>(value) {
+                        Some(fps) if fps > 0.0 => fps,
+                        _ => panic!("--fps should be a positive number (got \"{}\")", value)
+                    };
+                }
+
+                "--dylib-dir"  => options.dylib_dir      = Path::new(value!()),
+                "--asset-dir"  => options.asset_dir      = Path::new(value!()),
+                "--config-dir" => options.config_dir     = Path::new(value!()),
+                "--save-dir"   => options.save_state_dir = Path::new(value!()),
+                "--load-state" => options.start_state    = Some(Path::new(value!())),
+
+                "--headless" => options.headless = Some(Path::new(value!())),
+
+                "--frames" => {
+                    let value = value!();
+                    options.headless_frames = match from_str::<uint>(value) {
+                        Some(frames) => frames,
+                        None => panic!("--frames should be a number of frames (got \"{}\")", value)
+                    };
+                }
+
+                "--dump" => options.dump_path = Some(Path::new(value!())),
+
+                other => println!("WARNING: Ignoring unknown argument {}", other)
+            }
+        }
+
+        options
+    }
 }
-static TIMERR_NOCANDO: uint = 97;
 
 fn main() {
     // test_static();
+    let host_options = HostOptions::from_args(os::args().as_slice());
+    platform::set_asset_dir(&host_options.asset_dir);
+    platform::set_config_dir(&host_options.config_dir);
+
+    match host_options.headless {
+        Some(ref script) => {
+            headless::run(&host_options, script);
+            return;
+        }
+        None => {}
+    }
 
     let glfw = glfw::init(glfw::FAIL_ON_ERRORS).unwrap();
 
-    let (window, event) = glfw
-        .create_window(480, 480, "The Flying Dutchman's Prisoner", glfw::WindowMode::Windowed)
-        .expect("OH GOD WHY");
+    let (width, height) = (host_options.window_width, host_options.window_height);
+    let created = if host_options.fullscreen {
+        glfw.with_primary_monitor(|monitor| match monitor {
+            Some(monitor) => glfw.create_window(width, height, WINDOW_TITLE,
+                                                glfw::WindowMode::FullScreen(monitor)),
+            None => {
+                println!("WARNING: No monitor to go fullscreen on. Opening a window instead.");
+                glfw.create_window(width, height, WINDOW_TITLE, glfw::WindowMode::Windowed)
+            }
+        })
+    } else {
+        glfw.create_window(width, height, WINDOW_TITLE, glfw::WindowMode::Windowed)
+    };
+    let (window, event) = created.expect("OH GOD WHY");
 
     window.set_key_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_scroll_polling(true);
     window.set_size_polling(true);
+    window.set_close_polling(true);
     window.make_current();
 
-    unsafe {
-        glfwSetTestIdent(99);
-        println!(".exe: Test ident: {}", glfwTestIdent());
-        println!(".exe: glfwGetCurrentContext(): {}", glfwGetCurrentContext());
-    }
-
-    let mut current_dylib_path = game_dylib_path().unwrap();
-    let mut lib = load_game_dylib_from(&current_dylib_path);
+    let mut game_lib = match open_game_lib(&host_options.dylib_dir)
+                              .and_then(|lib| check_fits_in_memory(&lib.layout).map(|_| lib)) {
+        Ok(lib) => lib,
+        Err(e)  => panic!("Can't run the game library: {}", e)
+    };
 
-    let mut load      = unsafe { uninitialized() };
-    let mut test_loop = unsafe { uninitialized() };
-    match test_loop_fn(&lib) {
-        (load_fn, test_loop_fn) => {
-            load      = load_fn;
-            test_loop = test_loop_fn;
-        }
-    }
+    let memory_block = reserve_memory();
+    let (game_memory, mut memory) = split_memory(&memory_block);
 
     // TODO Stack memory is nice, but might want to box it if it gets too big.
-    let mut game_memory    = [0u8, ..4096];
-    let mut options_memory = [0u8, ..512];
-    let mut gl_memory      = [0u8, ..1024];
-    // let mut game_memory = box [0u8, ..2048 * 1024];
+    let mut options_memory = [0u8, ..OPTIONS_MEMORY_SIZE];
+    let mut gl_memory      = [0u8, ..GL_MEMORY_SIZE];
 
-    unsafe {
-        load(true, &_glfw, &window, &mut game_memory[0], &mut options_memory[0], &mut gl_memory[0]);
+    let (width, height) = window.get_size();
+    (game_lib.load)(true, &platform::SERVICES, width, height, &mut game_memory[0], &mut memory,
+                    &mut options_memory[0], &mut gl_memory[0]);
+
+    match host_options.start_state {
+        Some(ref path) => {
+            match save_state::load(path, &game_lib.layout, game_memory,
+                                   options_memory.as_mut_slice(), &mut memory) {
+                Ok(()) => println!("Starting from {}", path.display()),
+                Err(e) => panic!("Can't start from {}: {}", path.display(), e)
+            }
+            (game_lib.load)(false, &platform::SERVICES, width, height, &mut game_memory[0], &mut memory,
+                            &mut options_memory[0], &mut gl_memory[0]);
+        }
+        None => {}
     }
 
-    let (tx, rx) = channel();
-    spawn(move || watch_for_updated_dll(&tx));
+    let rx = watch_for_reloads(host_options.dylib_dir.clone());
+    let mut input_loop = InputLoop::new();
+    let mut rollback: Option<Rollback> = None;
+    let mut save_state_keys_down = [false, ..4];
 
-    let target_frame_time = Duration::nanoseconds((1.0e9 / 60.0 as f64).floor() as i64);
-    // let delta_frame_time = target_frame_time + Duration::microseconds(400);
+    let target_frame_time = Duration::nanoseconds((1.0e9 / host_options.target_fps).floor() as i64);
+    let pacer = FramePacer::new(host_options.frame_pacing, target_frame_time, &glfw);
     let mut delta = target_frame_time.clone();
-    let should_sleep = unsafe { timeBeginPeriod(1) != TIMERR_NOCANDO };
 
     while !window.should_close() {
         let time = Duration::span(|| {
+            let mut just_swapped = false;
+            // NOTE Options can't change layout across a reload, so neither
+            // can this.
+            let input_size = game_lib.layout.options_input_size as uint;
+
             match rx.try_recv() {
-                Ok((new_lib_path, new_lib, new_load, new_test_loop)) => {
-                    lib       = new_lib;
-                    load      = new_load;
-                    test_loop = new_test_loop;
-
-                    loop {
-                        match fs::unlink(&current_dylib_path) {
-                            Err(e) => continue,
-                            _ => break
-                        }
+                // NOTE checked against the layout that's actually loaded, which
+                // a rollback or a refused migration can leave behind.
+                Ok(new_lib) => match check_compatible(&game_lib.layout, &new_lib.layout) {
+                    Err(e) => {
+                        println!("REFUSING TO LOAD {}: {}",
+                                 new_lib.path.as_ref().unwrap().display(), e);
+                        println!("Restart the game to pick up this build.");
                     }
-                    current_dylib_path = new_lib_path;
 
-                    load(false, &_glfw, &window,
-                        &mut game_memory[0], &mut options_memory[0], &mut gl_memory[0]
-                    );
+                    Ok(reload) => {
+                        match reload {
+                            Reload::MigrateGame => println!("Game changed layout; migrating."),
+                            Reload::InPlace     => {}
+                        }
+
+                        // Done with whatever the last reload left around.
+                        match rollback.take() {
+                            Some(previous) => remove_dylib(previous.lib),
+                            None => {}
+                        }
+
+                        let snapshot = MemorySnapshot::take(game_memory, &memory,
+                                                            options_memory.slice_to(input_size),
+                                                            gl_memory.as_slice());
+                        let swap = match reload {
+                            Reload::InPlace     => true,
+                            Reload::MigrateGame => migrate_game(&game_lib, &new_lib, game_memory)
+                        };
+
+                        if swap {
+                            // The snapshot is laid out for the old Game.
+                            match reload {
+                                Reload::MigrateGame => input_loop.stop(options_memory.slice_to_mut(input_size)),
+                                Reload::InPlace     => {}
+                            }
+
+                            let old_lib = mem::replace(&mut game_lib, new_lib);
+                            rollback = Some(Rollback {
+                                lib:         old_lib,
+                                memory:      snapshot,
+                                frames_left: ROLLBACK_FRAMES
+                            });
+                            just_swapped = true;
+                        }
+                    }
                 },
                 _ => {}
             }
 
-            test_loop(
-                &mut game_memory[0],
-                &mut options_memory[0],
-                &mut gl_memory[0],
-                &delta, &glfw, &window, &event
-            );
+            let frame_result = guard(rollback.is_some(), || {
+                if just_swapped {
+                    let (width, height) = window.get_size();
+                    (game_lib.load)(false, &platform::SERVICES, width, height,
+                        &mut game_memory[0], &mut memory, &mut options_memory[0], &mut gl_memory[0]
+                    );
+                }
+
+                let game_input = input::poll(&glfw, &window, &event);
+
+                // NOTE this runs during playback too, so the game still
+                // hears about resizes and quitting. Playback then puts the
+                // recorded actions over whatever this made of the input.
+                if (game_lib.process_input)(&mut options_memory[0], &mut gl_memory[0], &game_input) {
+                    window.set_should_close(true);
+                }
+
+                let frame_delta = if input_loop.playing() {
+                    input_loop.play_frame(game_memory, &mut memory,
+                                          options_memory.slice_to_mut(input_size))
+                } else {
+                    if input_loop.recording() {
+                        input_loop.record(options_memory.slice_to(input_size), &delta);
+                    }
+                    delta.clone()
+                };
+
+                (game_lib.update)(&mut game_memory[0], &mut memory, &options_memory[0], &frame_delta);
+                (game_lib.render)(&game_memory[0], &mut gl_memory[0], &mut memory);
+                window.swap_buffers();
+            });
+
+            match frame_result {
+                Ok(()) => {
+                    let settled = match rollback {
+                        Some(ref mut r) => { r.frames_left -= 1; r.frames_left == 0 }
+                        None => false
+                    };
+                    if settled {
+                        remove_dylib(rollback.take().unwrap().lib);
+                    }
+                }
+
+                Err(reason) => {
+                    let Rollback { lib: old_lib, memory: snapshot, .. } = rollback.take().unwrap();
+                    let bad_lib = mem::replace(&mut game_lib, old_lib);
+
+                    println!("ERROR: The new game library panicked: {}", reason);
+                    println!("Rolling back to {}.", game_lib.path.as_ref().unwrap().display());
+
+                    input_loop.stop(options_memory.slice_to_mut(input_size));
+                    snapshot.restore(game_memory, &mut memory,
+                                     options_memory.slice_to_mut(input_size),
+                                     gl_memory.as_mut_slice());
+                    remove_dylib(bad_lib);
+
+                    // NOTE the new library may have replaced GL objects (like
+                    // the shader program) that the restored GlData refers to.
+                    let (width, height) = window.get_size();
+                    (game_lib.load)(false, &platform::SERVICES, width, height,
+                                    &mut game_memory[0], &mut memory,
+                                    &mut options_memory[0], &mut gl_memory[0]);
+                }
+            }
+
+            input_loop.check_key(window.get_key(Key::L) == Action::Press,
+                                 game_memory, &memory, options_memory.slice_to_mut(input_size));
+
+            for (slot, &key) in SAVE_STATE_KEYS.iter().enumerate() {
+                let key_down = window.get_key(key) == Action::Press;
+                let pressed = key_down && !save_state_keys_down[slot];
+                save_state_keys_down[slot] = key_down;
+                if !pressed { continue; }
+
+                let path = save_state::path_for(&host_options.save_state_dir, slot);
+                let shift_down = window.get_key(Key::LeftShift) == Action::Press ||
+                                 window.get_key(Key::RightShift) == Action::Press;
+                if shift_down {
+                    match save_state::save(&path, &game_lib.layout, game_memory,
+                                           options_memory.as_slice(), &memory) {
+                        Ok(()) => println!("Saved state to {}", path.display()),
+                        Err(e) => println!("ERROR: Couldn't save state {}: {}", slot, e)
+                    }
+                }
+                else {
+                    input_loop.stop(options_memory.slice_to_mut(input_size));
+                    match save_state::load(&path, &game_lib.layout, game_memory,
+                                           options_memory.as_mut_slice(), &mut memory) {
+                        Ok(()) => {
+                            println!("Loaded state from {}", path.display());
+                            let (width, height) = window.get_size();
+                            (game_lib.load)(false, &platform::SERVICES, width, height,
+                                            &mut game_memory[0], &mut memory,
+                                            &mut options_memory[0], &mut gl_memory[0]);
+                        }
+                        Err(e) => println!("REFUSING TO LOAD STATE {}: {}", slot, e)
+                    }
+                }
+            }
         });
 
-        if time > target_frame_time {
-            delta = time;
-        }// else {
-         //   delta = time + Duration::span(|| {
-         //       if should_sleep {
-         //           sleep(target_frame_time - time);
-         //       }
-         //   });
-        // }
+        // NOTE the game simulates in fixed ticks, so this is the real frame
+        // time, not target_frame_time.
+        delta = pacer.finish_frame(time);
     }
 
-    if should_sleep {
-        unsafe { timeEndPeriod(1); }
-    }
     println!("Hey, I compiled and ran!");
 }
@@ -0,0 +1,112 @@
+// Keeps the main loop from running (much) faster than it needs to.
+
+extern crate time;
+
+use glfw;
+use std::io::timer::sleep;
+use std::time::duration::Duration;
+
+#[deriving(Copy, Show, PartialEq)]
+pub enum FramePacing {
+    // Let swap_buffers block on the monitor's refresh.
+    Vsync,
+    // Sleep (and then spin for the last bit) until the target frame time.
+    Sleep,
+    // Run as fast as possible.
+    Uncapped
+}
+
+impl FramePacing {
+    pub fn from_str(s: &str) -> Option<FramePacing> {
+        match s {
+            "vsync"    => Some(FramePacing::Vsync),
+            "sleep"    => Some(FramePacing::Sleep),
+            "uncapped" => Some(FramePacing::Uncapped),
+            _          => None
+        }
+    }
+}
+
+// NOTE OS sleeps tend to overshoot by up to a scheduler tick, so we stop
+// sleeping this far short of the target and spin the rest of the way.
+fn spin_margin(fine_timer: bool) -> Duration {
+    if fine_timer { Duration::microseconds(1500) } else { Duration::milliseconds(3) }
+}
+
+pub struct FramePacer {
+    pub mode: FramePacing,
+    pub target_frame_time: Duration,
+    fine_timer: bool
+}
+
+impl FramePacer {
+    // NOTE the window's context needs to be current for the swap interval
+    // to take.
+    pub fn new(mode: FramePacing, target_frame_time: Duration, glfw: &glfw::Glfw) -> FramePacer {
+        glfw.set_swap_interval(match mode { FramePacing::Vsync => 1, _ => 0 });
+
+        let fine_timer = match mode {
+            FramePacing::Sleep => begin_fine_timer(),
+            _ => false
+        };
+
+        FramePacer { mode: mode, target_frame_time: target_frame_time, fine_timer: fine_timer }
+    }
+
+    // Waits out whatever is left of the frame after work_time, and returns
+    // how long the whole frame took.
+    pub fn finish_frame(&self, work_time: Duration) -> Duration {
+        match self.mode {
+            FramePacing::Sleep if work_time < self.target_frame_time => {
+                let start = time::precise_time_ns();
+                let remaining = self.target_frame_time - work_time;
+
+                let margin = spin_margin(self.fine_timer);
+                if remaining > margin {
+                    sleep(remaining - margin);
+                }
+                while Duration::nanoseconds((time::precise_time_ns() - start) as i64) < remaining {}
+
+                work_time + Duration::nanoseconds((time::precise_time_ns() - start) as i64)
+            }
+
+            _ => work_time
+        }
+    }
+}
+
+impl Drop for FramePacer {
+    fn drop(&mut self) {
+        if self.fine_timer {
+            end_fine_timer();
+        }
+    }
+}
+
+// === Winapi stuff for sleep resolution. ===
+#[cfg(target_os = "windows")]
+#[link(name = "Winmm")]
+extern "C" {
+    pub fn timeBeginPeriod(period: uint) -> uint;
+    pub fn timeEndPeriod(period: uint) -> uint;
+}
+#[cfg(target_os = "windows")]
+static TIMERR_NOCANDO: uint = 97;
+
+// NOTE Windows sleeps in ~15ms steps unless asked for 1ms resolution.
+#[cfg(target_os = "windows")]
+fn begin_fine_timer() -> bool {
+    unsafe { timeBeginPeriod(1) != TIMERR_NOCANDO }
+}
+
+#[cfg(target_os = "windows")]
+fn end_fine_timer() {
+    unsafe { timeEndPeriod(1); }
+}
+
+// Everywhere else sleep is already fine grained.
+#[cfg(not(target_os = "windows"))]
+fn begin_fine_timer() -> bool { true }
+
+#[cfg(not(target_os = "windows"))]
+fn end_fine_timer() {}
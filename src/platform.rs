@@ -0,0 +1,174 @@
+// The platform services the game goes through for files, logging, time and
+// GL, so that it never touches the OS (or GLFW) itself.
+
+extern crate time;
+
+use glfw;
+use libc::{c_void, c_char};
+use std::io::File;
+use std::io::fs::PathExtensions;
+use std::mem;
+use std::os;
+use std::slice;
+use std::str;
+
+pub type GetProcAddressFn = extern "C" fn(*const c_char) -> *const c_void;
+
+// NOTE make sure this matches platform::PlatformServices in the game.
+#[repr(C)]
+#[allow(missing_copy_implementations)]
+pub struct PlatformServices {
+    pub read_file: extern "C" fn(path: *const u8, path_len: u64,
+                                 buffer: *mut u8, buffer_len: u64) -> i64,
+    pub write_file: extern "C" fn(path: *const u8, path_len: u64,
+                                  data: *const u8, data_len: u64) -> bool,
+    pub modified_time: extern "C" fn(path: *const u8, path_len: u64) -> i64,
+    pub asset_path: extern "C" fn(name: *const u8, name_len: u64,
+                                  buffer: *mut u8, buffer_len: u64) -> u64,
+    pub config_path: extern "C" fn(name: *const u8, name_len: u64,
+                                   buffer: *mut u8, buffer_len: u64) -> u64,
+    pub log: extern "C" fn(message: *const u8, message_len: u64),
+    pub precise_time_ns: extern "C" fn() -> u64,
+    pub get_proc_address: GetProcAddressFn
+}
+
+pub static SERVICES: PlatformServices = PlatformServices {
+    read_file:        read_file,
+    write_file:       write_file,
+    modified_time:    modified_time,
+    asset_path:       asset_path,
+    config_path:      config_path,
+    log:              log,
+    precise_time_ns:  precise_time_ns,
+    get_proc_address: get_proc_address
+};
+
+// NOTE these are set once at startup and never freed, since the game can
+// ask for paths for as long as it runs.
+static mut ASSET_DIR:  *const Path = 0 as *const Path;
+static mut CONFIG_DIR: *const Path = 0 as *const Path;
+
+fn leak_absolute(dir: &Path, kind: &str) -> *const Path {
+    match os::make_absolute(dir) {
+        Ok(dir) => unsafe { mem::transmute(box dir) },
+        Err(e)  => panic!("Couldn't find {} directory {}: {}", kind, dir.display(), e)
+    }
+}
+
+pub fn set_asset_dir(dir: &Path) {
+    unsafe { ASSET_DIR = leak_absolute(dir, "asset"); }
+}
+
+pub fn set_config_dir(dir: &Path) {
+    unsafe { CONFIG_DIR = leak_absolute(dir, "config"); }
+}
+
+unsafe fn raw_str<'a>(data: &'a *const u8, len: u64) -> Option<&'a str> {
+    str::from_utf8(slice::from_raw_buf(data, len as uint))
+}
+
+// Copies bytes into the game's buffer, as long as they fit.
+fn copy_out(bytes: &[u8], buffer: *mut u8, buffer_len: u64) -> bool {
+    if bytes.len() as u64 > buffer_len { return false; }
+    unsafe {
+        let buf = slice::from_raw_mut_buf(&buffer, bytes.len());
+        slice::bytes::copy_memory(buf, bytes);
+    }
+    true
+}
+
+extern "C" fn read_file(path: *const u8, path_len: u64, buffer: *mut u8, buffer_len: u64) -> i64 {
+    let path = match unsafe { raw_str(&path, path_len) } {
+        Some(p) => Path::new(p),
+        None    => return -1
+    };
+
+    let size = match path.stat() {
+        Ok(stat) => stat.size,
+        Err(_)   => return -1
+    };
+    // Just asking how big it is.
+    if size > buffer_len { return size as i64; }
+
+    match File::open(&path).and_then(|mut file| file.read_to_end()) {
+        Ok(data) => {
+            copy_out(data.as_slice(), buffer, buffer_len);
+            data.len() as i64
+        }
+        Err(e) => {
+            println!("ERROR: Couldn't read {}: {}", path.display(), e);
+            -1
+        }
+    }
+}
+
+extern "C" fn write_file(path: *const u8, path_len: u64, data: *const u8, data_len: u64) -> bool {
+    let path = match unsafe { raw_str(&path, path_len) } {
+        Some(p) => Path::new(p),
+        None    => return false
+    };
+    let data = unsafe { slice::from_raw_buf(&data, data_len as uint) };
+
+    match File::create(&path).and_then(|mut file| file.write(data)) {
+        Ok(()) => true,
+        Err(e) => {
+            println!("ERROR: Couldn't write {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+extern "C" fn modified_time(path: *const u8, path_len: u64) -> i64 {
+    let path = match unsafe { raw_str(&path, path_len) } {
+        Some(p) => Path::new(p),
+        None    => return -1
+    };
+
+    match path.stat() {
+        Ok(stat) => stat.modified as i64,
+        Err(_)   => -1
+    }
+}
+
+// Writes dir joined with name into the game's buffer.
+fn path_in(dir: *const Path, kind: &str,
+           name: *const u8, name_len: u64, buffer: *mut u8, buffer_len: u64) -> u64 {
+    let name = match unsafe { raw_str(&name, name_len) } {
+        Some(n) => n,
+        None    => return 0
+    };
+
+    // NOTE no panicking in here; it would unwind into the game.
+    if dir.is_null() {
+        println!("ERROR: The game asked for {} before there was {} directory.", name, kind);
+        return 0;
+    }
+    let path = unsafe { (*dir).join(name) };
+
+    let path_bytes = path.as_vec();
+    if copy_out(path_bytes, buffer, buffer_len) { path_bytes.len() as u64 } else { 0 }
+}
+
+extern "C" fn asset_path(name: *const u8, name_len: u64, buffer: *mut u8, buffer_len: u64) -> u64 {
+    path_in(unsafe { ASSET_DIR }, "an asset", name, name_len, buffer, buffer_len)
+}
+
+extern "C" fn config_path(name: *const u8, name_len: u64, buffer: *mut u8, buffer_len: u64) -> u64 {
+    path_in(unsafe { CONFIG_DIR }, "a config", name, name_len, buffer, buffer_len)
+}
+
+extern "C" fn log(message: *const u8, message_len: u64) {
+    match unsafe { raw_str(&message, message_len) } {
+        Some(message) => println!("{}", message),
+        None          => println!("(game logged something that isn't UTF-8)")
+    }
+}
+
+extern "C" fn precise_time_ns() -> u64 {
+    time::precise_time_ns()
+}
+
+// NOTE the window's context has to be current.
+extern "C" fn get_proc_address(name: *const c_char) -> *const c_void {
+    unsafe { glfw::ffi::glfwGetProcAddress(name) }
+}
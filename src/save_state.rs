@@ -0,0 +1,145 @@
+// Save states: the whole of game memory dumped to a numbered file, to get
+// back to some exact situation later (even in another session).
+//
+// File layout (all little endian):
+//     "DPSTATE1"
+//     LayoutDescriptor, field by field
+//     memory base: u64
+//     game size: u64, options size: u64, permanent arena used: u64
+//     game memory, options memory, permanent arena
+//
+// NOTE the assets arena isn't saved. It goes with the textures the game has
+// loaded right now, whatever session the state came from.
+
+use std::io;
+use std::io::{File, IoResult};
+use std::io::fs;
+use std::io::fs::PathExtensions;
+use std::slice::bytes::copy_memory;
+
+use {LayoutDescriptor, GameMemory};
+
+static MAGIC: &'static [u8] = b"DPSTATE1";
+
+pub fn path_for(dir: &Path, slot: uint) -> Path {
+    dir.join(format!("state-{}.dpstate", slot))
+}
+
+fn write_layout(file: &mut File, layout: &LayoutDescriptor) -> IoResult<()> {
+    try!(file.write_le_u32(layout.abi_version));
+    try!(file.write_le_u64(layout.game_size));
+    try!(file.write_le_u64(layout.game_hash));
+    try!(file.write_le_u64(layout.options_size));
+    try!(file.write_le_u64(layout.options_hash));
+    try!(file.write_le_u64(layout.options_input_size));
+    try!(file.write_le_u64(layout.gl_data_size));
+    file.write_le_u64(layout.gl_data_hash)
+}
+
+fn read_layout(file: &mut File) -> IoResult<LayoutDescriptor> {
+    Ok(LayoutDescriptor {
+        abi_version:  try!(file.read_le_u32()),
+        game_size:    try!(file.read_le_u64()),
+        game_hash:    try!(file.read_le_u64()),
+        options_size: try!(file.read_le_u64()),
+        options_hash: try!(file.read_le_u64()),
+        options_input_size: try!(file.read_le_u64()),
+        gl_data_size: try!(file.read_le_u64()),
+        gl_data_hash: try!(file.read_le_u64())
+    })
+}
+
+pub fn save(path:           &Path,
+            layout:         &LayoutDescriptor,
+            game_memory:    &[u8],
+            options_memory: &[u8],
+            memory:         &GameMemory)
+            -> IoResult<()>
+{
+    try!(fs::mkdir_recursive(&path.dir_path(), io::USER_RWX));
+    let mut file = try!(File::create(path));
+
+    let permanent = memory.permanent.used_bytes();
+
+    try!(file.write(MAGIC));
+    try!(write_layout(&mut file, layout));
+    try!(file.write_le_u64(memory.permanent.base as u64));
+    try!(file.write_le_u64(game_memory.len() as u64));
+    try!(file.write_le_u64(options_memory.len() as u64));
+    try!(file.write_le_u64(permanent.len() as u64));
+
+    try!(file.write(game_memory));
+    try!(file.write(options_memory));
+    file.write(permanent)
+}
+
+// Only the Game and Options layouts have to match. GlData isn't saved,
+// since none of the GL objects in it would survive a restart anyway.
+fn check_layout(saved: &LayoutDescriptor, current: &LayoutDescriptor) -> Result<(), String> {
+    if saved.abi_version != current.abi_version {
+        return Err(format!("it was saved with ABI version {}, this is {}",
+                           saved.abi_version, current.abi_version));
+    }
+    if saved.game_size != current.game_size || saved.game_hash != current.game_hash {
+        return Err("Game has changed layout since it was saved".to_string());
+    }
+    if saved.options_size != current.options_size || saved.options_hash != current.options_hash {
+        return Err("Options has changed layout since it was saved".to_string());
+    }
+    Ok(())
+}
+
+// Nothing gets written to memory unless the whole file checks out.
+pub fn load(path:           &Path,
+            layout:         &LayoutDescriptor,
+            game_memory:    &mut [u8],
+            options_memory: &mut [u8],
+            memory:         &mut GameMemory)
+            -> Result<(), String>
+{
+    if !path.exists() {
+        return Err(format!("{} doesn't exist", path.display()));
+    }
+
+    macro_rules! io_try(
+        ($e:expr) => (match $e {
+            Ok(v)  => v,
+            Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e))
+        })
+    );
+
+    let mut file = io_try!(File::open(path));
+
+    if io_try!(file.read_exact(MAGIC.len())).as_slice() != MAGIC {
+        return Err(format!("{} is not a save state", path.display()));
+    }
+    try!(check_layout(&io_try!(read_layout(&mut file)), layout));
+
+    let base = io_try!(file.read_le_u64());
+    if base != memory.permanent.base as u64 {
+        return Err(format!("it was saved with memory at {:x}, but it's at {:x} now",
+                           base, memory.permanent.base as u64));
+    }
+
+    let game_len      = io_try!(file.read_le_u64()) as uint;
+    let options_len   = io_try!(file.read_le_u64()) as uint;
+    let permanent_len = io_try!(file.read_le_u64()) as uint;
+    if game_len != game_memory.len() || options_len != options_memory.len() ||
+       permanent_len > memory.permanent.size
+    {
+        return Err("memory block sizes don't match this build of the host".to_string());
+    }
+
+    let game_data      = io_try!(file.read_exact(game_len));
+    let options_data   = io_try!(file.read_exact(options_len));
+    let permanent_data = io_try!(file.read_exact(permanent_len));
+
+    // NOTE only the input part of Options; the bindings and devices stay
+    // however they are right now.
+    let input_len = layout.options_input_size as uint;
+    copy_memory(game_memory, game_data.as_slice());
+    copy_memory(options_memory.slice_to_mut(input_len), options_data.slice_to(input_len));
+    memory.permanent.restore(permanent_data.as_slice());
+
+    Ok(())
+}